@@ -1,126 +1,393 @@
-use chrono::{Duration, Utc};
-use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use reqwest::RequestBuilder;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::storage::UsageSnapshot;
+use super::{
+    attach_billed, http, keep_days, roll_up, Billed, BucketWidth, DateRange, FetchContext,
+    FetchResult, ProviderError, UsageAcc, UsageProvider,
+};
+use crate::pricing;
+use crate::storage::{Unit, UsageKind, UsageSnapshot};
+use crate::timezone;
+
+pub struct AnthropicProvider;
 
+#[async_trait]
+impl UsageProvider for AnthropicProvider {
+    fn id(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn required_settings(&self) -> &'static [&'static str] {
+        &["anthropic_key"]
+    }
+
+    async fn fetch(
+        &self,
+        ctx: &FetchContext,
+        range: DateRange,
+    ) -> Result<FetchResult, ProviderError> {
+        let api_key = ctx.require("anthropic_key")?;
+        fetch_anthropic_usage(api_key, range, ctx.tz).await
+    }
+}
+
+const API_BASE: &str = "https://api.anthropic.com/v1/organizations";
+
+/// Pages to follow before giving up on a single report. At 168 hourly buckets
+/// per page this covers a year.
+const MAX_PAGES: usize = 60;
+
+/// One page of `usage_report/messages` or `cost_report`; both share the
+/// bucketed, paginated shape.
 #[derive(Debug, Deserialize)]
-struct AnthropicUsageResponse {
-    data: Option<Vec<Value>>,
+struct ReportPage {
+    #[serde(default)]
+    data: Vec<ReportBucket>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
 }
 
-/// Pricing per 1M tokens (input / output) as of early 2025 — best-effort.
-fn model_price(model: &str) -> (f64, f64) {
-    // (input_per_mtok, output_per_mtok)
-    if model.contains("claude-opus-4") || model.contains("claude-opus-3-5") {
-        (15.0, 75.0)
-    } else if model.contains("claude-sonnet-4") || model.contains("claude-sonnet-3-5") {
-        (3.0, 15.0)
-    } else if model.contains("claude-haiku-4") || model.contains("claude-haiku-3-5") {
-        (0.8, 4.0)
-    } else if model.contains("claude-haiku-3") {
-        (0.25, 1.25)
-    } else if model.contains("claude-opus") {
-        (15.0, 75.0)
-    } else if model.contains("claude-sonnet") {
-        (3.0, 15.0)
-    } else if model.contains("claude-haiku") {
-        (0.25, 1.25)
-    } else {
-        (3.0, 15.0) // default to sonnet pricing
+/// A one-day or one-hour bucket, with one entry in `results` per group
+/// (model, or cost line item).
+#[derive(Debug, Deserialize)]
+struct ReportBucket {
+    starting_at: String,
+    #[serde(default)]
+    results: Vec<Value>,
+}
+
+impl ReportBucket {
+    fn start(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.starting_at)
+            .ok()
+            .map(|t| t.to_utc())
     }
 }
 
-pub async fn fetch_anthropic_usage(api_key: &str) -> Result<Vec<UsageSnapshot>, String> {
-    if api_key.is_empty() {
-        return Ok(vec![]);
+struct Report {
+    buckets: Vec<ReportBucket>,
+    /// Set when `MAX_PAGES` was reached with pages still remaining.
+    truncated: bool,
+}
+
+/// Split prompt-cache writes into `(5-minute, 1-hour)` TTL buckets. Older
+/// payloads only carry the combined `cache_creation_input_tokens`, which is
+/// counted as 5-minute writes.
+pub fn cache_creation_tokens(usage: &Value) -> (i64, i64) {
+    let get = |v: &Value, key: &str| v.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+    match usage.get("cache_creation") {
+        Some(split) if split.is_object() => (
+            get(split, "ephemeral_5m_input_tokens"),
+            get(split, "ephemeral_1h_input_tokens"),
+        ),
+        _ => (get(usage, "cache_creation_input_tokens"), 0),
     }
+}
 
-    let client = Client::new();
-    let now = Utc::now();
-    let start = (now - Duration::days(30))
-        .format("%Y-%m-%dT00:00:00Z")
-        .to_string();
-    let end = now.format("%Y-%m-%dT23:59:59Z").to_string();
+/// Fetch every page of a report under `API_BASE`, in `width` buckets, for
+/// the days of `range` as they fall in `tz`.
+async fn fetch_report(
+    api_key: &str,
+    endpoint: &str,
+    group_by: &[&str],
+    width: BucketWidth,
+    range: DateRange,
+    tz: Tz,
+) -> Result<Report, ProviderError> {
+    let (start, end) = range.instants(tz);
+    let starting_at = start.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let ending_at = end.format("%Y-%m-%dT%H:%M:%SZ").to_string();
 
-    let url = "https://api.anthropic.com/v1/organizations/usage_report/messages";
-    let response = client
-        .get(url)
+    let mut query = vec![
+        ("starting_at", starting_at.as_str()),
+        ("ending_at", ending_at.as_str()),
+        ("bucket_width", width.as_str()),
+        ("limit", width.page_limit()),
+    ];
+    query.extend(group_by.iter().map(|g| ("group_by[]", *g)));
+    let request = http::client()
+        .get(format!("{API_BASE}/{endpoint}"))
         .header("x-api-key", api_key)
         .header("anthropic-version", "2023-06-01")
-        .query(&[
-            ("start_time", start.as_str()),
-            ("end_time", end.as_str()),
-            ("granularity", "1d"),
-            ("group_by", "model"),
-        ])
-        .send()
-        .await
-        .map_err(|e| format!("Anthropic request failed: {e}"))?;
+        .query(&query);
+    fetch_pages(request, endpoint).await
+}
+
+/// Follow `next_page` from `request` for up to `MAX_PAGES` pages.
+async fn fetch_pages(request: RequestBuilder, endpoint: &str) -> Result<Report, ProviderError> {
+    let mut buckets = Vec::new();
+    let mut page: Option<String> = None;
+
+    for _ in 0..MAX_PAGES {
+        let mut this_page = request
+            .try_clone()
+            .ok_or_else(|| ProviderError::Other("request cannot be repeated".to_string()))?;
+        if let Some(token) = &page {
+            this_page = this_page.query(&[("page", token)]);
+        }
+        let body: ReportPage = serde_json::from_value(http::get_json(this_page).await?)
+            .map_err(|e| ProviderError::Parse(format!("Anthropic {endpoint}: {e}")))?;
+        buckets.extend(body.data);
+
+        match body.next_page {
+            Some(next) if body.has_more => page = Some(next),
+            _ => {
+                return Ok(Report {
+                    buckets,
+                    truncated: false,
+                })
+            }
+        }
+    }
 
-    let status = response.status();
-    let body: Value = response
-        .json()
-        .await
-        .map_err(|e| format!("Anthropic parse error: {e}"))?;
+    Ok(Report {
+        buckets,
+        truncated: true,
+    })
+}
+
+pub async fn fetch_anthropic_usage(
+    api_key: &str,
+    range: DateRange,
+    tz: Tz,
+) -> Result<FetchResult, ProviderError> {
+    let report = fetch_report(
+        api_key,
+        "usage_report/messages",
+        &["model", "service_tier"],
+        BucketWidth::Hour,
+        range,
+        tz,
+    )
+    .await?;
 
-    if !status.is_success() {
-        return Err(format!("Anthropic API error {status}: {body}"));
+    let mut acc = UsageAcc::new();
+    let mut covered: HashSet<NaiveDate> = HashSet::new();
+    for bucket in &report.buckets {
+        let Some(start) = bucket.start() else { continue };
+        covered.insert(timezone::local_date(start, tz));
+        let hour = start.format("%Y-%m-%dT%H:00:00Z").to_string();
+        for result in &bucket.results {
+            add_result(&mut acc, &hour, result);
+        }
     }
+    let (hourly, mut daily) = roll_up(acc, tz);
 
-    let fetched_at = Utc::now().to_rfc3339();
-    let mut snapshots = Vec::new();
+    // Pages run oldest first, so hitting the cap leaves the newest days
+    // unfetched; report those rather than showing them as zero.
+    let missing_days = if report.truncated {
+        range.days().filter(|d| !covered.contains(d)).collect()
+    } else {
+        Vec::new()
+    };
 
-    if let Some(data) = body.get("data").and_then(|d| d.as_array()) {
-        for item in data {
-            let model = item
+    // Billed amounts are a bonus on top of the usage report; without them
+    // we still have the token-based estimates.
+    let mut warnings = Vec::new();
+    match fetch_anthropic_costs(api_key, range, tz).await {
+        Ok(billed) => attach_billed(&mut daily, billed, empty_snapshot),
+        Err(e) => warnings.push(format!("Cost report unavailable: {e}")),
+    }
+
+    keep_days(&mut daily, range);
+    let mut snapshots: Vec<UsageSnapshot> = daily.into_values().collect();
+    snapshots.iter_mut().for_each(pricing::price_snapshot);
+    Ok(FetchResult {
+        snapshots,
+        hourly,
+        missing_days,
+        warnings,
+        ..Default::default()
+    })
+}
+
+/// Billed USD per `(date, model, kind)` from the cost report, split by
+/// service tier like the usage. Charges that are not tied to a model (web
+/// search, code execution) are keyed by their cost type.
+pub async fn fetch_anthropic_costs(
+    api_key: &str,
+    range: DateRange,
+    tz: Tz,
+) -> Result<Billed, ProviderError> {
+    // The cost report only comes in daily buckets
+    let report = fetch_report(
+        api_key,
+        "cost_report",
+        &["description"],
+        BucketWidth::Day,
+        range,
+        tz,
+    )
+    .await?;
+    Ok(billed_amounts(&report, tz))
+}
+
+fn billed_amounts(report: &Report, tz: Tz) -> Billed {
+    let mut billed = HashMap::new();
+    for bucket in &report.buckets {
+        let Some(start) = bucket.start() else { continue };
+        let date = timezone::bucket_date(start, tz)
+            .format("%Y-%m-%d")
+            .to_string();
+        for result in &bucket.results {
+            // Amounts are decimal strings in cents
+            let Some(cents) = result
+                .get("amount")
+                .and_then(|v| v.as_str())
+                .and_then(|v| v.parse::<f64>().ok())
+            else {
+                continue;
+            };
+            let model = result
                 .get("model")
                 .and_then(|v| v.as_str())
-                .unwrap_or("unknown")
+                .or_else(|| result.get("cost_type").and_then(|v| v.as_str()))
+                .unwrap_or("other")
                 .to_string();
+            *billed
+                .entry((date.clone(), model, tier_kind(result)))
+                .or_insert(0.0) += cents / 100.0;
+        }
+    }
+    billed
+}
 
-            let date = item
-                .get("timestamp")
-                .and_then(|v| v.as_str())
-                .map(|s| &s[..10])
-                .unwrap_or("1970-01-01")
-                .to_string();
+fn empty_snapshot(date: &str, model: String, kind: UsageKind) -> UsageSnapshot {
+    UsageSnapshot {
+        id: None,
+        provider: "anthropic".to_string(),
+        model,
+        date: date.to_string(),
+        input_tokens: 0,
+        output_tokens: 0,
+        cache_tokens: 0,
+        cache_creation_5m_tokens: 0,
+        cache_creation_1h_tokens: 0,
+        cost_usd: 0.0,
+        estimated_cost_usd: 0.0,
+        billed_cost_usd: None,
+        usage_kind: kind,
+        unit: Unit::Tokens,
+        quantity: 0.0,
+        fetched_at: Utc::now().to_rfc3339(),
+        unpriced: false,
+    }
+}
 
-            let input_tokens = item
-                .get("input_tokens")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-            let output_tokens = item
-                .get("output_tokens")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-            let cache_tokens = item
-                .get("cache_read_input_tokens")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-
-            // Use reported cost if available, otherwise estimate
-            let cost_usd = if let Some(c) = item.get("cost_usd").and_then(|v| v.as_f64()) {
-                c
-            } else {
-                let (inp_price, out_price) = model_price(&model);
-                (input_tokens as f64 * inp_price + output_tokens as f64 * out_price) / 1_000_000.0
-            };
+/// Fold one `results` row into the per-(hour, model, kind) totals. Rows are
+/// split by service tier; the batch tier counts as `Batch`, the others are
+/// summed as `Text`.
+fn add_result(acc: &mut UsageAcc, hour: &str, result: &Value) {
+    let get = |key: &str| result.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+    let model = result
+        .get("model")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+    // `uncached_input_tokens` excludes cache reads and writes, which are
+    // reported (and priced) separately
+    let input_tokens = result
+        .get("uncached_input_tokens")
+        .or_else(|| result.get("input_tokens"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0);
+    let (cache_creation_5m_tokens, cache_creation_1h_tokens) = cache_creation_tokens(result);
 
-            snapshots.push(UsageSnapshot {
-                id: None,
-                provider: "anthropic".to_string(),
-                model,
-                date,
-                input_tokens,
-                output_tokens,
-                cache_tokens,
-                cost_usd,
-                fetched_at: fetched_at.clone(),
-            });
-        }
+    let kind = tier_kind(result);
+    let entry = acc
+        .entry((hour.to_string(), model.clone(), kind))
+        .or_insert_with(|| empty_snapshot(&hour[..10], model, kind));
+    entry.input_tokens += input_tokens;
+    entry.output_tokens += get("output_tokens");
+    entry.cache_tokens += get("cache_read_input_tokens");
+    entry.cache_creation_5m_tokens += cache_creation_5m_tokens;
+    entry.cache_creation_1h_tokens += cache_creation_1h_tokens;
+}
+
+/// `Batch` for a result of the batch service tier, `Text` for the others.
+fn tier_kind(result: &Value) -> UsageKind {
+    match result.get("service_tier").and_then(|v| v.as_str()) {
+        Some("batch") => UsageKind::Batch,
+        _ => UsageKind::Text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::http::tests::{self as server, serve};
+
+    fn page(start: &str, next: Option<&str>) -> String {
+        server::json(
+            &json!({
+                "data": [{"starting_at": start, "results": []}],
+                "has_more": next.is_some(),
+                "next_page": next,
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn follows_next_page_to_the_last_page() {
+        let (url, requests) = serve(vec![
+            page("2025-03-01T00:00:00Z", Some("p2")),
+            page("2025-03-02T00:00:00Z", None),
+        ])
+        .await;
+        let report = fetch_pages(http::client().get(&url), "test").await.unwrap();
+        assert_eq!(report.buckets.len(), 2);
+        assert!(!report.truncated);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("page=p2"), "{}", requests[1]);
     }
 
-    Ok(snapshots)
+    #[tokio::test]
+    async fn stops_after_max_pages() {
+        let (url, requests) = serve(vec![page("2025-03-01T00:00:00Z", Some("more"))]).await;
+        let report = fetch_pages(http::client().get(&url), "test").await.unwrap();
+        assert!(report.truncated);
+        assert_eq!(report.buckets.len(), MAX_PAGES);
+        assert_eq!(requests.lock().unwrap().len(), MAX_PAGES);
+    }
+
+    #[test]
+    fn bills_cost_report_amounts_by_model_and_tier() {
+        let report = Report {
+            buckets: serde_json::from_value(json!([{
+                "starting_at": "2025-03-01T00:00:00Z",
+                "results": [
+                    {"amount": "250", "model": "claude-sonnet-4-5", "service_tier": "standard"},
+                    {"amount": "50", "model": "claude-sonnet-4-5", "service_tier": "standard"},
+                    {"amount": "100", "model": "claude-sonnet-4-5", "service_tier": "batch"},
+                    {"amount": "5", "cost_type": "web_search"},
+                    {"currency": "USD"},
+                ],
+            }]))
+            .unwrap(),
+            truncated: false,
+        };
+        let key = |model: &str, kind| ("2025-03-01".to_string(), model.to_string(), kind);
+
+        // Amounts are in cents; a daily UTC bucket keeps its date west of UTC
+        let billed = billed_amounts(&report, "America/Los_Angeles".parse().unwrap());
+        assert_eq!(billed.len(), 3);
+        assert_eq!(billed[&key("claude-sonnet-4-5", UsageKind::Text)], 3.0);
+        assert_eq!(billed[&key("claude-sonnet-4-5", UsageKind::Batch)], 1.0);
+        assert_eq!(billed[&key("web_search", UsageKind::Text)], 0.05);
+    }
 }
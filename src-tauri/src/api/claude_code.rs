@@ -1,29 +1,61 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 
-use chrono::{Duration, Utc};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
 use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::sync::Mutex;
 
-use crate::storage::UsageSnapshot;
-
-/// Pricing per 1M tokens (input / output) — mirrors anthropic.rs
-fn model_price(model: &str) -> (f64, f64) {
-    if model.contains("claude-opus-4") || model.contains("claude-opus-3-5") {
-        (15.0, 75.0)
-    } else if model.contains("claude-sonnet-4") || model.contains("claude-sonnet-3-5") {
-        (3.0, 15.0)
-    } else if model.contains("claude-haiku-4") || model.contains("claude-haiku-3-5") {
-        (0.8, 4.0)
-    } else if model.contains("claude-haiku-3") {
-        (0.25, 1.25)
-    } else if model.contains("claude-opus") {
-        (15.0, 75.0)
-    } else if model.contains("claude-sonnet") {
-        (3.0, 15.0)
-    } else if model.contains("claude-haiku") {
-        (0.25, 1.25)
-    } else {
-        (3.0, 15.0)
+use super::anthropic::cache_creation_tokens;
+use super::{
+    hour_of, keep_days, roll_up, DateRange, FetchContext, FetchResult, ProviderError, UsageAcc,
+    UsageProvider,
+};
+use crate::pricing;
+use crate::storage::{
+    ClaudeCodeEvent, LogBatch, LogCursor, SessionUsage, Storage, Unit, UsageKind, UsageSnapshot,
+};
+use crate::timezone;
+
+pub struct ClaudeCodeProvider;
+
+/// Provider ID stored on usage from subagent (Task) runs. It is not a
+/// provider of its own: the Claude Code provider fetches it, and the UI folds
+/// it back into Claude Code unless asked to show it separately.
+pub const SUBAGENT_PROVIDER: &str = "claude_code_subagents";
+
+/// How many directories below a project to look for logs. Subagent logs sit
+/// at `<session-uuid>/subagents/`, well within it.
+const MAX_DEPTH: usize = 4;
+
+/// Setting listing further Claude Code config directories, one per line,
+/// each optionally given a label as `label=path`.
+pub const CONFIG_DIRS_SETTING: &str = "claude_config_dirs";
+
+/// Held while logs are read, so two fetches never read the same new lines.
+static INGEST: Mutex<()> = Mutex::const_new(());
+
+#[async_trait]
+impl UsageProvider for ClaudeCodeProvider {
+    fn id(&self) -> &'static str {
+        "claude_code"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Claude Code"
+    }
+
+    async fn fetch(
+        &self,
+        ctx: &FetchContext,
+        range: DateRange,
+    ) -> Result<FetchResult, ProviderError> {
+        let roots = log_roots(&ctx.home_dir, &ctx.settings);
+        Ok(fetch_claude_code_usage(&ctx.storage, &roots, range, ctx.tz).await?)
     }
 }
 
@@ -32,137 +64,854 @@ struct Accumulator {
     input_tokens: i64,
     output_tokens: i64,
     cache_tokens: i64,
+    cache_creation_5m_tokens: i64,
+    cache_creation_1h_tokens: i64,
 }
 
-/// Read token usage from Claude Code's local session JSONL files.
-/// `home_dir` should be the user's home directory (e.g. `/home/user`).
-pub async fn fetch_claude_code_usage(home_dir: PathBuf) -> Result<Vec<UsageSnapshot>, String> {
-    let projects_dir = home_dir.join(".claude").join("projects");
-    if !projects_dir.exists() {
-        return Ok(vec![]);
+impl Accumulator {
+    fn add(&mut self, other: &Accumulator) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_tokens += other.cache_tokens;
+        self.cache_creation_5m_tokens += other.cache_creation_5m_tokens;
+        self.cache_creation_1h_tokens += other.cache_creation_1h_tokens;
     }
 
-    let cutoff = Utc::now() - Duration::days(30);
-    let cutoff_date = cutoff.format("%Y-%m-%d").to_string();
+    /// Every token, cache reads and writes included, since those are billed
+    /// too.
+    fn total(&self) -> i64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_tokens
+            + self.cache_creation_5m_tokens
+            + self.cache_creation_1h_tokens
+    }
+}
 
-    // Accumulate tokens keyed by (date, model)
-    let mut acc: HashMap<(String, String), Accumulator> = HashMap::new();
+/// Stored usage within the fetched range, summed the ways the fetch reports
+/// it.
+#[derive(Default)]
+struct SessionScan {
+    /// Tokens keyed by (hour, model, subagent).
+    acc: HashMap<(String, String, bool), Accumulator>,
+    /// Tokens keyed by (session, hour, model).
+    sessions: HashMap<(String, String, String), Accumulator>,
+    /// Project directory of each session.
+    projects: HashMap<String, String>,
+}
 
-    let mut project_entries = tokio::fs::read_dir(&projects_dir)
-        .await
-        .map_err(|e| format!("Cannot read ~/.claude/projects: {e}"))?;
+/// A Claude Code config directory whose session logs are read, and the
+/// profile its usage is labelled with.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRoot {
+    pub label: String,
+    pub dir: PathBuf,
+    /// Whether it holds a `projects` directory yet.
+    pub found: bool,
+}
 
-    while let Ok(Some(project_entry)) = project_entries.next_entry().await {
-        let Ok(ft) = project_entry.file_type().await else { continue };
-        if !ft.is_dir() {
-            continue;
-        }
+impl LogRoot {
+    fn new(label: String, dir: PathBuf) -> Self {
+        let found = dir.join("projects").is_dir();
+        LogRoot { label, dir, found }
+    }
 
-        let mut session_entries = match tokio::fs::read_dir(project_entry.path()).await {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+    /// Where Claude Code keeps the root's per-project session logs.
+    pub fn projects_dir(&self) -> PathBuf {
+        self.dir.join("projects")
+    }
+}
 
-        while let Ok(Some(session_entry)) = session_entries.next_entry().await {
-            let path = session_entry.path();
-            // Only process .jsonl files directly inside the project directory
-            // (skips <session-uuid>/ subdirectories that contain subagent logs)
-            if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
-                continue;
+/// Every config directory to read logs from: those listed in the
+/// [`CONFIG_DIRS_SETTING`] setting, then `~/.claude`, `~/.config/claude` and
+/// `CLAUDE_CONFIG_DIR`. A directory reached twice, e.g. through a symlink, is
+/// read once, under the first label it was given. Unlabelled directories are
+/// labelled with their path.
+pub fn log_roots(home_dir: &Path, settings: &HashMap<String, String>) -> Vec<LogRoot> {
+    let configured = settings
+        .get(CONFIG_DIRS_SETTING)
+        .map(String::as_str)
+        .unwrap_or("")
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() {
+                return None;
             }
-            // Skip files not modified in the last 30 days for performance
-            if let Ok(meta) = session_entry.metadata().await {
-                if let Ok(modified) = meta.modified() {
-                    let age = std::time::SystemTime::now()
-                        .duration_since(modified)
-                        .unwrap_or_default();
-                    if age.as_secs() > 30 * 24 * 3600 {
-                        continue;
-                    }
+            let (label, path) = match line.split_once('=') {
+                Some((label, path)) if !label.trim().is_empty() => {
+                    (Some(label.trim().to_string()), path.trim())
                 }
-            }
-            parse_session_file(&path, &cutoff_date, &mut acc).await;
-        }
+                _ => (None, line),
+            };
+            let dir = expand_home(path, home_dir);
+            let label = label.unwrap_or_else(|| display_path(&dir, home_dir));
+            Some(LogRoot::new(label, dir))
+        });
+    let discovered = [
+        home_dir.join(".claude"),
+        home_dir.join(".config").join("claude"),
+    ]
+    .into_iter()
+    .chain(
+        std::env::var("CLAUDE_CONFIG_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(|dir| expand_home(dir.trim(), home_dir)),
+    )
+    .map(|dir| LogRoot::new(display_path(&dir, home_dir), dir));
+
+    let mut seen = HashSet::new();
+    configured
+        .chain(discovered)
+        .filter(|root| seen.insert(root.dir.canonicalize().unwrap_or_else(|_| root.dir.clone())))
+        .collect()
+}
+
+fn expand_home(path: &str, home_dir: &Path) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some("") => home_dir.to_path_buf(),
+        Some(rest) if rest.starts_with(['/', '\\']) => home_dir.join(&rest[1..]),
+        _ => PathBuf::from(path),
     }
+}
+
+/// `path` with the home directory shortened to `~`.
+fn display_path(path: &Path, home_dir: &Path) -> String {
+    match path.strip_prefix(home_dir) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Read any new lines from the Claude Code session JSONL files under `roots`
+/// into storage, then report the stored usage within `range`, along with any
+/// earlier days the new lines fell on. Events are assigned to days as they
+/// fall in `tz`.
+pub async fn fetch_claude_code_usage(
+    storage: &Storage,
+    roots: &[LogRoot],
+    range: DateRange,
+    tz: Tz,
+) -> Result<FetchResult, String> {
+    let ingested = ingest_logs(storage, roots).await?;
 
+    // Hours are UTC, so select the UTC hours the range's local days span.
+    // Lines about older days (a newly added root, a log synced late) would
+    // otherwise never reach those days' totals.
+    let mut days = range;
+    if let Some(earliest) = ingested
+        .earliest_hour
+        .and_then(|hour| DateTime::parse_from_rfc3339(&hour).ok())
+    {
+        days.start = days.start.min(timezone::local_date(earliest.to_utc(), tz));
+    }
+    let (range_start, range_end) = days.instants(tz);
+    let usage = storage
+        .claude_code_usage(
+            range_start.format("%Y-%m-%dT%H:00:00Z").to_string(),
+            range_end.format("%Y-%m-%dT%H:00:00Z").to_string(),
+        )
+        .await?;
+
+    let mut scan = SessionScan::default();
+    for row in usage {
+        let tokens = Accumulator {
+            input_tokens: row.input_tokens,
+            output_tokens: row.output_tokens,
+            cache_tokens: row.cache_tokens,
+            cache_creation_5m_tokens: row.cache_creation_5m_tokens,
+            cache_creation_1h_tokens: row.cache_creation_1h_tokens,
+        };
+        scan.sessions
+            .entry((row.session_id.clone(), row.hour.clone(), row.model.clone()))
+            .or_default()
+            .add(&tokens);
+        scan.acc
+            .entry((row.hour, row.model, row.subagent))
+            .or_default()
+            .add(&tokens);
+        scan.projects.insert(row.session_id, row.project);
+    }
+
+    // Subagent usage is stored under its own provider so the UI can show it
+    // apart from the sessions that started it
     let fetched_at = Utc::now().to_rfc3339();
-    let mut snapshots: Vec<UsageSnapshot> = acc
+    let mut main = UsageAcc::new();
+    let mut subagents = UsageAcc::new();
+    for ((hour, model, subagent), data) in scan.acc {
+        if data.total() == 0 {
+            continue;
+        }
+        let provider = if subagent {
+            SUBAGENT_PROVIDER
+        } else {
+            "claude_code"
+        };
+        let snapshot = UsageSnapshot {
+            id: None,
+            provider: provider.to_string(),
+            model: model.clone(),
+            date: hour[..10].to_string(),
+            input_tokens: data.input_tokens,
+            output_tokens: data.output_tokens,
+            cache_tokens: data.cache_tokens,
+            cache_creation_5m_tokens: data.cache_creation_5m_tokens,
+            cache_creation_1h_tokens: data.cache_creation_1h_tokens,
+            cost_usd: 0.0,
+            estimated_cost_usd: 0.0,
+            billed_cost_usd: None,
+            usage_kind: UsageKind::Text,
+            unit: Unit::Tokens,
+            quantity: 0.0,
+            fetched_at: fetched_at.clone(),
+            unpriced: false,
+        };
+        let target = if subagent { &mut subagents } else { &mut main };
+        target.insert((hour, model, UsageKind::Text), snapshot);
+    }
+    let (mut hourly, mut daily) = roll_up(main, tz);
+    let (subagent_hourly, mut subagent_daily) = roll_up(subagents, tz);
+    hourly.extend(subagent_hourly);
+    keep_days(&mut daily, days);
+    keep_days(&mut subagent_daily, days);
+
+    let mut snapshots: Vec<UsageSnapshot> = daily
+        .into_values()
+        .chain(subagent_daily.into_values())
+        .collect();
+    snapshots.iter_mut().for_each(pricing::price_snapshot);
+    snapshots.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(FetchResult {
+        snapshots,
+        hourly,
+        sessions: session_rows(scan.sessions, &scan.projects, days, tz, &fetched_at),
+        missing_days: Vec::new(),
+        duplicates_dropped: ingested.duplicates,
+        warnings: ingested.errors,
+    })
+}
+
+/// Sum per-session hours into priced per-day rows, for the days in `days`
+/// only, like [`keep_days`].
+fn session_rows(
+    sessions: HashMap<(String, String, String), Accumulator>,
+    projects: &HashMap<String, String>,
+    days: DateRange,
+    tz: Tz,
+    fetched_at: &str,
+) -> Vec<SessionUsage> {
+    let mut daily: HashMap<(String, String, String), Accumulator> = HashMap::new();
+    for ((session_id, hour, model), data) in sessions {
+        if data.total() == 0 {
+            continue;
+        }
+        let Ok(start) = DateTime::parse_from_rfc3339(&hour) else { continue };
+        let day = timezone::local_date(start.to_utc(), tz);
+        if !days.contains(day) {
+            continue;
+        }
+        let date = day.format("%Y-%m-%d").to_string();
+        daily
+            .entry((session_id, date, model))
+            .or_default()
+            .add(&data);
+    }
+
+    let mut rows: Vec<SessionUsage> = daily
         .into_iter()
-        .filter(|(_, data)| data.input_tokens > 0 || data.output_tokens > 0)
-        .map(|((date, model), data)| {
-            let (inp_price, out_price) = model_price(&model);
-            let cost_usd = (data.input_tokens as f64 * inp_price
-                + data.output_tokens as f64 * out_price)
-                / 1_000_000.0;
-            UsageSnapshot {
-                id: None,
-                provider: "claude_code".to_string(),
-                model,
-                date,
-                input_tokens: data.input_tokens,
-                output_tokens: data.output_tokens,
-                cache_tokens: data.cache_tokens,
-                cost_usd,
-                fetched_at: fetched_at.clone(),
-            }
+        .map(|((session_id, date, model), data)| SessionUsage {
+            project: projects.get(&session_id).cloned().unwrap_or_default(),
+            session_id,
+            model,
+            date,
+            input_tokens: data.input_tokens,
+            output_tokens: data.output_tokens,
+            cache_tokens: data.cache_tokens,
+            cache_creation_5m_tokens: data.cache_creation_5m_tokens,
+            cache_creation_1h_tokens: data.cache_creation_1h_tokens,
+            cost_usd: 0.0,
+            unpriced: false,
+            fetched_at: fetched_at.to_string(),
         })
         .collect();
+    rows.iter_mut().for_each(pricing::price_session);
+    rows
+}
 
-    snapshots.sort_by(|a, b| a.date.cmp(&b.date));
-    Ok(snapshots)
+/// A JSONL log somewhere under a project directory.
+struct SessionLog {
+    path: PathBuf,
+    /// The session the log belongs to: the file's own name for a top-level
+    /// log, the `<session-uuid>/` directory it sits in otherwise.
+    session_id: String,
+    /// Project path guessed from the directory name, for logs that never
+    /// record a `cwd`.
+    project: String,
+    /// Label of the config directory the log was found in.
+    profile: String,
+    /// Found inside a `<session-uuid>/` directory rather than at the top
+    /// level, i.e. written by a subagent of that session.
+    subagent: bool,
 }
 
-async fn parse_session_file(
-    path: &PathBuf,
-    cutoff_date: &str,
-    acc: &mut HashMap<(String, String), Accumulator>,
-) {
-    let content = match tokio::fs::read_to_string(path).await {
-        Ok(c) => c,
-        Err(_) => return,
-    };
+/// Claude Code names each project directory after the project's path with
+/// separators replaced by `-`. Dashes in the path itself come back as `/`,
+/// so this is only a fallback for the `cwd` the session logs.
+fn decode_project_dir(name: &str) -> String {
+    name.replace('-', "/")
+}
 
-    for line in content.lines() {
-        if line.trim().is_empty() {
-            continue;
+/// Every `.jsonl` log under `project_dir`, down to [`MAX_DEPTH`]. Symlinked
+/// directories are not followed.
+async fn session_logs(project_dir: PathBuf, profile: &str) -> Vec<SessionLog> {
+    let project = project_dir
+        .file_name()
+        .map(|name| decode_project_dir(&name.to_string_lossy()))
+        .unwrap_or_default();
+    let mut logs = Vec::new();
+    // (directory, depth, the session it belongs to below the top level)
+    let mut pending: Vec<(PathBuf, usize, Option<String>)> = vec![(project_dir, 0, None)];
+    while let Some((dir, depth, session)) = pending.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else { continue };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(ft) = entry.file_type().await else { continue };
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if ft.is_dir() {
+                if depth + 1 < MAX_DEPTH {
+                    let session = session.clone().unwrap_or(name);
+                    pending.push((path, depth + 1, Some(session)));
+                }
+            } else if let Some(stem) = name.strip_suffix(".jsonl") {
+                logs.push(SessionLog {
+                    session_id: session.clone().unwrap_or_else(|| stem.to_string()),
+                    project: project.clone(),
+                    profile: profile.to_string(),
+                    subagent: session.is_some(),
+                    path,
+                });
+            }
         }
-        let Ok(val): Result<Value, _> = serde_json::from_str(line) else {
-            continue;
+    }
+    logs
+}
+
+/// Drop what was read from the logs so the next fetch reads them all again
+/// from the start. Waits for a read in progress, whose cursors would
+/// otherwise be written back afterwards.
+pub async fn reset_logs(storage: &Storage) -> Result<(), String> {
+    let _guard = INGEST.lock().await;
+    storage.clear_log_cursors().await
+}
+
+/// What [`ingest_logs`] read.
+#[derive(Default)]
+struct Ingested {
+    /// Responses skipped as copies of ones already stored: resumed and
+    /// forked sessions copy earlier responses into their own file, and
+    /// streaming can log one response over several lines.
+    duplicates: u64,
+    /// Earliest hour any of the new lines was logged in.
+    earliest_hour: Option<String>,
+    /// Logs that could not be read, with why.
+    errors: Vec<String>,
+}
+
+/// Read the lines appended to every log under `roots` since its cursor.
+/// Roots without a `projects` directory are skipped. Cursors of logs that
+/// were not found are dropped.
+async fn ingest_logs(storage: &Storage, roots: &[LogRoot]) -> Result<Ingested, String> {
+    let _guard = INGEST.lock().await;
+    let mut cursors = storage.log_cursors().await?;
+    let mut ingested = Ingested::default();
+
+    for root in roots {
+        let projects_dir = root.projects_dir();
+        let mut project_entries = match tokio::fs::read_dir(&projects_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Cannot read {}: {e}", projects_dir.display())),
         };
-        if val.get("type").and_then(|t| t.as_str()) != Some("assistant") {
-            continue;
+
+        while let Ok(Some(project_entry)) = project_entries.next_entry().await {
+            let Ok(ft) = project_entry.file_type().await else { continue };
+            if !ft.is_dir() {
+                continue;
+            }
+
+            for log in session_logs(project_entry.path(), &root.label).await {
+                let cursor = cursors.remove(log.path.to_string_lossy().as_ref());
+                match read_new_lines(&log, cursor).await {
+                    Ok(Some(batch)) => {
+                        if let Some(hour) = batch.events.iter().map(|e| &e.hour).min() {
+                            if ingested.earliest_hour.as_ref().is_none_or(|h| hour < h) {
+                                ingested.earliest_hour = Some(hour.clone());
+                            }
+                        }
+                        ingested.duplicates += storage.ingest_log(batch).await?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => ingested
+                        .errors
+                        .push(format!("Cannot read {}: {e}", log.path.display())),
+                }
+            }
         }
-        let Some(message) = val.get("message") else { continue };
-        let Some(usage) = message.get("usage") else { continue };
+    }
+    // Whatever is left belongs to logs that were deleted or whose config
+    // directory is no longer read; should one come back it is read from the
+    // start, and its responses are recognised as already stored
+    if !cursors.is_empty() {
+        storage
+            .delete_log_cursors(cursors.into_keys().collect())
+            .await?;
+    }
+    Ok(ingested)
+}
 
-        let timestamp = val
-            .get("timestamp")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-        if timestamp.len() < 10 {
-            continue;
+/// The complete lines added to `log` since `cursor`, or `None` if it has not
+/// changed. A log that was replaced, truncated or rewritten in place is read
+/// again from the start; responses read before are recognised by their IDs.
+/// A last line without its newline is still being written and is left for
+/// the next read.
+async fn read_new_lines(
+    log: &SessionLog,
+    cursor: Option<LogCursor>,
+) -> std::io::Result<Option<LogBatch>> {
+    let mut file = tokio::fs::File::open(&log.path).await?;
+    let meta = file.metadata().await?;
+    let inode = inode(&meta);
+    let size = meta.len();
+
+    let resume = match cursor {
+        Some(c) if c.inode == inode && c.offset <= size => {
+            if c.size == size {
+                return Ok(None);
+            }
+            ends_with_line(&mut file, &c).await?.then_some(c)
         }
-        let date = &timestamp[..10];
-        if date < cutoff_date {
-            continue;
+        _ => None,
+    };
+    let (mut offset, mut last_line_len, mut last_line_hash) = match resume {
+        Some(c) => (c.offset, c.last_line_len, c.last_line_hash),
+        None => (0, 0, line_hash(b"")),
+    };
+
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut events = Vec::new();
+    let mut cwd = None;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).await?;
+        if read == 0 || line.last() != Some(&b'\n') {
+            break;
         }
+        if let Some(event) = parse_line(&line, log, &mut cwd) {
+            events.push(event);
+        }
+        offset += read as u64;
+        last_line_len = read as u64;
+        last_line_hash = line_hash(&line);
+    }
+
+    Ok(Some(LogBatch {
+        cursor: LogCursor {
+            path: log.path.to_string_lossy().into_owned(),
+            inode,
+            size,
+            offset,
+            last_line_len,
+            last_line_hash,
+        },
+        events,
+        session_id: log.session_id.clone(),
+        profile: log.profile.clone(),
+        project_guessed: cwd.is_none(),
+        project: cwd.unwrap_or_else(|| log.project.clone()),
+    }))
+}
+
+/// Whether the line the cursor stopped after is still in place.
+async fn ends_with_line(file: &mut tokio::fs::File, cursor: &LogCursor) -> std::io::Result<bool> {
+    if cursor.offset == 0 {
+        return Ok(true);
+    }
+    let Some(start) = cursor.offset.checked_sub(cursor.last_line_len) else {
+        return Ok(false);
+    };
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut line = vec![0; cursor.last_line_len as usize];
+    file.read_exact(&mut line).await?;
+    Ok(line_hash(&line) == cursor.last_line_hash)
+}
+
+#[cfg(unix)]
+fn inode(meta: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.ino()
+}
+
+// There is no stable inode outside unix, but a replacement file is created
+// anew, so its creation time tells it apart
+#[cfg(not(unix))]
+fn inode(meta: &std::fs::Metadata) -> u64 {
+    meta.created()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it is stable across Rust releases,
+/// so stored hashes stay comparable.
+fn line_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// The usage an assistant line reports, noting the first `cwd` seen.
+fn parse_line(line: &[u8], log: &SessionLog, cwd: &mut Option<String>) -> Option<ClaudeCodeEvent> {
+    let val: Value = serde_json::from_slice(line).ok()?;
+    if cwd.is_none() {
+        *cwd = val.get("cwd").and_then(|v| v.as_str()).map(str::to_string);
+    }
+    if val.get("type").and_then(|t| t.as_str()) != Some("assistant") {
+        return None;
+    }
+    let message = val.get("message")?;
+    let usage = message.get("usage")?;
+
+    let timestamp = val.get("timestamp").and_then(|v| v.as_str()).unwrap_or("");
+    let hour = hour_of(timestamp)?;
 
-        let model = message
+    // Lines without both IDs are keyed by their content, so re-reading a
+    // rewritten log or a byte-identical copy doesn't count them again
+    let message_id = message.get("id").and_then(|v| v.as_str());
+    let request_id = val.get("requestId").and_then(|v| v.as_str());
+    let (message_id, request_id) = match (message_id, request_id) {
+        (Some(m), Some(r)) => (m.to_string(), r.to_string()),
+        _ => (String::new(), format!("line:{}", line_hash(line))),
+    };
+
+    // Older versions logged subagent turns into the parent's own file,
+    // flagged as a sidechain
+    let sidechain = val.get("isSidechain").and_then(|v| v.as_bool()) == Some(true);
+    let (cache_creation_5m_tokens, cache_creation_1h_tokens) = cache_creation_tokens(usage);
+    Some(ClaudeCodeEvent {
+        message_id,
+        request_id,
+        session_id: log.session_id.clone(),
+        subagent: log.subagent || sidechain,
+        model: message
             .get("model")
             .and_then(|v| v.as_str())
             .unwrap_or("unknown")
-            .to_string();
-
-        let input_tokens = usage.get("input_tokens").and_then(|v| v.as_i64()).unwrap_or(0);
-        let output_tokens = usage.get("output_tokens").and_then(|v| v.as_i64()).unwrap_or(0);
-        let cache_tokens = usage
+            .to_string(),
+        hour,
+        input_tokens: usage
+            .get("input_tokens")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0),
+        output_tokens: usage
+            .get("output_tokens")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0),
+        cache_tokens: usage
             .get("cache_read_input_tokens")
             .and_then(|v| v.as_i64())
-            .unwrap_or(0);
+            .unwrap_or(0),
+        cache_creation_5m_tokens,
+        cache_creation_1h_tokens,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::{temp_dir, temp_storage};
+
+    fn session_log(path: PathBuf) -> SessionLog {
+        let session_id = path.file_stem().unwrap().to_string_lossy().into_owned();
+        SessionLog {
+            path,
+            session_id,
+            project: "/home/me/app".to_string(),
+            profile: "~/.claude".to_string(),
+            subagent: false,
+        }
+    }
+
+    /// An assistant line for one response; `ids` are the message and request
+    /// IDs, if it has them.
+    fn line(ids: Option<(&str, &str)>, output_tokens: i64) -> String {
+        let mut line = serde_json::json!({
+            "type": "assistant",
+            "timestamp": "2025-03-01T10:15:00.000Z",
+            "message": {
+                "model": "claude-sonnet-4-5",
+                "usage": { "input_tokens": 10, "output_tokens": output_tokens },
+            },
+        });
+        if let Some((message_id, request_id)) = ids {
+            line["message"]["id"] = message_id.into();
+            line["requestId"] = request_id.into();
+        }
+        line.to_string() + "\n"
+    }
+
+    async fn ingest(storage: &Storage, log: &SessionLog) -> u64 {
+        let cursor = storage
+            .log_cursors()
+            .await
+            .unwrap()
+            .remove(log.path.to_str().unwrap());
+        match read_new_lines(log, cursor).await.unwrap() {
+            Some(batch) => storage.ingest_log(batch).await.unwrap(),
+            None => 0,
+        }
+    }
+
+    /// Stored (input, output) tokens over every hour.
+    async fn stored_tokens(storage: &Storage) -> (i64, i64) {
+        let usage = storage
+            .claude_code_usage("0000".to_string(), "9999".to_string())
+            .await
+            .unwrap();
+        usage.iter().fold((0, 0), |(input, output), u| {
+            (input + u.input_tokens, output + u.output_tokens)
+        })
+    }
+
+    #[tokio::test]
+    async fn keeps_the_final_usage_of_a_streamed_response() {
+        let storage = temp_storage();
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        let streamed = [
+            line(Some(("m1", "r1")), 1),
+            line(Some(("m1", "r1")), 250),
+            line(Some(("m2", "r2")), 5),
+        ];
+        std::fs::write(&log.path, streamed.concat()).unwrap();
+
+        assert_eq!(ingest(&storage, &log).await, 1);
+        assert_eq!(stored_tokens(&storage).await, (20, 255));
+    }
+
+    #[tokio::test]
+    async fn counts_copied_responses_once() {
+        let storage = temp_storage();
+        let dir = temp_dir();
+        let lines = [line(Some(("m1", "r1")), 5), line(None, 7)].concat();
+        // A resumed session starts its own log with the earlier responses
+        let original = session_log(dir.join("s1.jsonl"));
+        let resumed = session_log(dir.join("s2.jsonl"));
+        std::fs::write(&original.path, &lines).unwrap();
+        std::fs::write(&resumed.path, lines + &line(None, 9)).unwrap();
+
+        assert_eq!(ingest(&storage, &original).await, 0);
+        assert_eq!(ingest(&storage, &resumed).await, 2);
+        assert_eq!(stored_tokens(&storage).await, (30, 21));
+    }
+
+    #[tokio::test]
+    async fn reads_every_log_again_after_a_reset() {
+        let storage = temp_storage();
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        std::fs::write(&log.path, line(Some(("m1", "r1")), 5)).unwrap();
+        ingest(&storage, &log).await;
+
+        reset_logs(&storage).await.unwrap();
+        assert!(storage.log_cursors().await.unwrap().is_empty());
+        assert_eq!(stored_tokens(&storage).await, (0, 0));
+        // Read from the start, and not mistaken for a copy of itself
+        assert_eq!(ingest(&storage, &log).await, 0);
+        assert_eq!(stored_tokens(&storage).await, (10, 5));
+    }
+
+    #[tokio::test]
+    async fn leaves_out_the_day_before_a_half_hour_zone_range() {
+        let storage = temp_storage();
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        // 23:40 on 1 March and 00:40 on 2 March in Kolkata, both read as part
+        // of the UTC hours 2 March spans
+        let at = |time: &str, ids| line(Some(ids), 5).replace("2025-03-01T10:15:00.000Z", time);
+        let lines = [
+            at("2025-03-01T18:10:00.000Z", ("m1", "r1")),
+            at("2025-03-01T19:10:00.000Z", ("m2", "r2")),
+        ];
+        std::fs::write(&log.path, lines.concat()).unwrap();
+        ingest(&storage, &log).await;
+
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 3, 2).unwrap();
+        let range = DateRange {
+            start: day,
+            end: day,
+        };
+        let tz = "Asia/Kolkata".parse().unwrap();
+        let result = fetch_claude_code_usage(&storage, &[], range, tz)
+            .await
+            .unwrap();
+        let snapshot_dates: HashSet<_> = result.snapshots.iter().map(|s| &s.date).collect();
+        let session_dates: HashSet<_> = result.sessions.iter().map(|s| &s.date).collect();
+        assert_eq!(snapshot_dates, HashSet::from([&"2025-03-02".to_string()]));
+        assert_eq!(session_dates, snapshot_dates);
+        assert_eq!(result.snapshots[0].output_tokens, 5);
+    }
+
+    #[test]
+    fn keys_lines_without_ids_by_their_content() {
+        let log = session_log(PathBuf::from("s1.jsonl"));
+        let key = |line: &str| {
+            let event = parse_line(line.as_bytes(), &log, &mut None).unwrap();
+            (event.message_id, event.request_id)
+        };
+        assert_eq!(key(&line(None, 7)), key(&line(None, 7)));
+        assert_ne!(key(&line(None, 7)), key(&line(None, 8)));
+        assert_eq!(
+            key(&line(Some(("m1", "r1")), 7)),
+            ("m1".to_string(), "r1".to_string())
+        );
+    }
+
+    /// Output tokens of each line read, which the tests below use to tell
+    /// lines apart.
+    async fn read(log: &SessionLog, cursor: Option<LogCursor>) -> Option<(Vec<i64>, LogCursor)> {
+        let batch = read_new_lines(log, cursor).await.unwrap()?;
+        let outputs = batch.events.iter().map(|e| e.output_tokens).collect();
+        Some((outputs, batch.cursor))
+    }
+
+    #[tokio::test]
+    async fn reads_complete_lines_added_since_the_cursor() {
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        let (a, b, c) = (line(None, 1), line(None, 2), line(None, 3));
+        // The last line is still being written
+        std::fs::write(&log.path, format!("{a}{}", &b[..20])).unwrap();
+        let (outputs, cursor) = read(&log, None).await.unwrap();
+        assert_eq!(outputs, [1]);
+        assert_eq!(cursor.offset, a.len() as u64);
+
+        std::fs::write(&log.path, format!("{a}{b}{c}")).unwrap();
+        let (outputs, cursor) = read(&log, Some(cursor)).await.unwrap();
+        assert_eq!(outputs, [2, 3]);
+        assert!(read(&log, Some(cursor)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rereads_a_truncated_log() {
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        std::fs::write(&log.path, [line(None, 1), line(None, 2)].concat()).unwrap();
+        let (_, cursor) = read(&log, None).await.unwrap();
+
+        // Compacted down to a single line
+        std::fs::write(&log.path, line(None, 9)).unwrap();
+        let (outputs, _) = read(&log, Some(cursor)).await.unwrap();
+        assert_eq!(outputs, [9]);
+    }
+
+    #[tokio::test]
+    async fn rereads_a_log_rewritten_in_place() {
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        std::fs::write(&log.path, [line(None, 1), line(None, 2)].concat()).unwrap();
+        let (_, cursor) = read(&log, None).await.unwrap();
+
+        let rewritten = [line(None, 7), line(None, 8), line(None, 9)];
+        std::fs::write(&log.path, rewritten.concat()).unwrap();
+        let (outputs, _) = read(&log, Some(cursor)).await.unwrap();
+        assert_eq!(outputs, [7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn rereads_a_replaced_log() {
+        let log = session_log(temp_dir().join("s1.jsonl"));
+        std::fs::write(&log.path, [line(None, 1), line(None, 2)].concat()).unwrap();
+        let (_, cursor) = read(&log, None).await.unwrap();
+
+        // Same leading lines, but in a different file
+        let replaced = [line(None, 1), line(None, 2), line(None, 3)];
+        std::fs::write(&log.path, replaced.concat()).unwrap();
+        let stale = LogCursor {
+            inode: cursor.inode + 1,
+            ..cursor
+        };
+        let (outputs, _) = read(&log, Some(stale)).await.unwrap();
+        assert_eq!(outputs, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn forgets_the_cursors_of_deleted_logs() {
+        let storage = temp_storage();
+        let dir = temp_dir();
+        let project = dir.join("projects").join("-home-me-app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("s1.jsonl"), line(None, 1)).unwrap();
+        std::fs::write(project.join("s2.jsonl"), line(None, 2)).unwrap();
+        let roots = [LogRoot::new("~/.claude".to_string(), dir)];
+
+        ingest_logs(&storage, &roots).await.unwrap();
+        assert_eq!(storage.log_cursors().await.unwrap().len(), 2);
+
+        std::fs::remove_file(project.join("s2.jsonl")).unwrap();
+        ingest_logs(&storage, &roots).await.unwrap();
+        let cursors = storage.log_cursors().await.unwrap();
+        let kept: Vec<_> = cursors.keys().collect();
+        assert_eq!(kept, [&project.join("s1.jsonl").to_string_lossy()]);
+    }
+
+    #[tokio::test]
+    async fn attributes_sessions_to_the_logged_cwd_over_the_directory_name() {
+        let storage = temp_storage();
+        let dir = temp_dir();
+        let project = dir.join("projects").join("-home-me-my-app");
+        std::fs::create_dir_all(&project).unwrap();
+        let mut logged: Value = serde_json::from_str(&line(None, 1)).unwrap();
+        logged["cwd"] = "/home/me/my-app".into();
+        std::fs::write(project.join("s1.jsonl"), logged.to_string() + "\n").unwrap();
+        std::fs::write(project.join("s2.jsonl"), line(None, 2)).unwrap();
+        let roots = [LogRoot::new("~/.claude".to_string(), dir)];
+
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let range = DateRange {
+            start: day,
+            end: day,
+        };
+        let result = fetch_claude_code_usage(&storage, &roots, range, chrono_tz::UTC)
+            .await
+            .unwrap();
+        let mut projects: Vec<_> = result
+            .sessions
+            .iter()
+            .map(|s| (s.session_id.as_str(), s.project.as_str(), s.output_tokens))
+            .collect();
+        projects.sort();
+        // Without a cwd, the dash in the directory name reads as a separator
+        assert_eq!(
+            projects,
+            [("s1", "/home/me/my-app", 1), ("s2", "/home/me/my/app", 2)]
+        );
+    }
+
+    #[test]
+    fn lists_configured_roots_until_the_setting_is_cleared() {
+        let home = temp_dir();
+        let labels = |value: Option<&str>| {
+            let settings = value
+                .map(|v| HashMap::from([(CONFIG_DIRS_SETTING.to_string(), v.to_string())]))
+                .unwrap_or_default();
+            let roots = log_roots(&home, &settings);
+            roots.into_iter().map(|r| r.label).collect::<Vec<_>>()
+        };
+        let discovered = labels(None);
+        assert_eq!(discovered[..2], ["~/.claude", "~/.config/claude"]);
 
-        let entry = acc.entry((date.to_string(), model)).or_default();
-        entry.input_tokens += input_tokens;
-        entry.output_tokens += output_tokens;
-        entry.cache_tokens += cache_tokens;
+        let configured = labels(Some("work = ~/work-claude\n\n~/.claude\n"));
+        assert_eq!(configured[0], "work");
+        assert_eq!(configured[1..], discovered[..]);
+        // Saving an emptied field removes the setting, as an empty one lists none
+        assert_eq!(labels(Some("")), discovered);
     }
 }
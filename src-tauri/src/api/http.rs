@@ -0,0 +1,187 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+
+use super::{check_response, ProviderError};
+
+/// Attempts per request, including the first.
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// Longest `Retry-After` we are willing to sit through inside one poll.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// Client shared by every HTTP provider, so connections are pooled across
+/// polls.
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Send an idempotent GET, retrying connection failures, 429, 5xx and
+/// Anthropic's 529 with jittered exponential backoff. A `Retry-After` header
+/// on those responses takes precedence over the computed delay.
+pub async fn send(request: RequestBuilder) -> Result<Response, ProviderError> {
+    let mut attempt = 1;
+    loop {
+        let this_try = request
+            .try_clone()
+            .ok_or_else(|| ProviderError::Other("request body cannot be retried".to_string()))?;
+        let retry_after = match this_try.send().await {
+            Ok(response) if is_retryable(response.status()) && attempt < MAX_ATTEMPTS => {
+                retry_after(response.headers())
+            }
+            Ok(response) => return check_response(response).await,
+            Err(e) if is_transient(&e) && attempt < MAX_ATTEMPTS => None,
+            Err(e) => return Err(ProviderError::Network(e.to_string())),
+        };
+        tokio::time::sleep(retry_after.unwrap_or_else(|| backoff(attempt))).await;
+        attempt += 1;
+    }
+}
+
+/// [`send`], then decode the body as JSON.
+pub async fn get_json(request: RequestBuilder) -> Result<Value, ProviderError> {
+    send(request)
+        .await?
+        .json()
+        .await
+        .map_err(|e| ProviderError::Parse(e.to_string()))
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.is_request()
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(|secs| Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+}
+
+/// Delay before retry number `attempt`: the exponential step, capped, with
+/// the upper half randomised so clients don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let step = BASE_DELAY
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_DELAY);
+    let half = step.as_millis() as u64 / 2;
+    Duration::from_millis(half + random_u64() % (half + 1))
+}
+
+fn random_u64() -> u64 {
+    // RandomState is seeded per instance, which is plenty for jitter
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serve `responses` in turn on a local port, repeating the last one.
+    /// Returns the base URL and the request line of every request made.
+    pub(crate) async fn serve(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let len = socket.read(&mut request).await.unwrap_or(0);
+                let text = String::from_utf8_lossy(&request[..len]);
+                let n = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(text.lines().next().unwrap_or_default().to_string());
+                    seen.len() - 1
+                };
+                let response = &responses[n.min(responses.len() - 1)];
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    /// A `200 OK` response carrying `body` as JSON.
+    pub(crate) fn json(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let (url, requests) = serve(vec![UNAVAILABLE.to_string()]).await;
+        let result = send(client().get(&url)).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::Upstream { status: 503, .. })
+        ));
+        assert_eq!(requests.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_until_a_response_succeeds() {
+        let (url, requests) = serve(vec![RATE_LIMITED.to_string(), json(r#"{"ok":true}"#)]).await;
+        let body = get_json(client().get(&url)).await.unwrap();
+        assert_eq!(body["ok"], true);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reads_and_caps_retry_after() {
+        let wait = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+            retry_after(&headers)
+        };
+        assert_eq!(wait(" 7 "), Some(Duration::from_secs(7)));
+        assert_eq!(wait("3600"), Some(MAX_RETRY_AFTER));
+        // The HTTP-date form falls back to the computed backoff
+        assert_eq!(wait("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let within = |attempt, low: u64, high: u64| {
+            let delay = backoff(attempt);
+            assert!(
+                Duration::from_millis(low) <= delay && delay <= Duration::from_millis(high),
+                "attempt {attempt}: {delay:?}"
+            );
+        };
+        within(1, 250, 500);
+        within(2, 500, 1_000);
+        within(3, 1_000, 2_000);
+        within(20, 15_000, 30_000);
+    }
+}
@@ -1,3 +1,539 @@
 pub mod anthropic;
 pub mod claude_code;
+pub mod http;
 pub mod openai;
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::pricing;
+use crate::storage::{HourlyUsage, SessionUsage, Storage, UsageKind, UsageSnapshot};
+use crate::timezone;
+
+/// Inclusive range of calendar days to fetch or query usage for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    /// The instants the range starts and ends (exclusive) in `tz`.
+    pub fn instants(&self, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            timezone::day_start(self.start, tz),
+            timezone::day_start(self.end + Duration::days(1), tz),
+        )
+    }
+
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        self.start <= day && day <= self.end
+    }
+
+    /// Every day in the range, oldest first.
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        let end = self.end;
+        self.start.iter_days().take_while(move |d| *d <= end)
+    }
+}
+
+/// Everything a provider may read from the environment during a fetch.
+pub struct FetchContext {
+    pub settings: HashMap<String, String>,
+    pub home_dir: PathBuf,
+    /// Zone whose calendar days usage is assigned to.
+    pub tz: Tz,
+    /// For providers that keep their own state between fetches.
+    pub storage: Storage,
+}
+
+impl FetchContext {
+    /// A non-empty setting value, or `MissingSetting` if it is unset.
+    pub fn require(&self, key: &'static str) -> Result<&str, ProviderError> {
+        match self.settings.get(key).map(|v| v.trim()) {
+            Some(v) if !v.is_empty() => Ok(v),
+            _ => Err(ProviderError::MissingSetting(key)),
+        }
+    }
+
+    pub fn has_settings(&self, keys: &[&str]) -> bool {
+        keys.iter()
+            .all(|k| self.settings.get(*k).is_some_and(|v| !v.trim().is_empty()))
+    }
+}
+
+/// Why a provider fetch failed. The variants separate problems the user has
+/// to fix (bad key) from ones that go away on their own (rate limits, outages).
+#[derive(Debug, Clone)]
+pub enum ProviderError {
+    MissingSetting(&'static str),
+    /// The API rejected the credentials (401/403).
+    Auth(String),
+    /// 429, with the server's `Retry-After` in seconds when it sent one.
+    RateLimited {
+        retry_after_secs: Option<u64>,
+    },
+    /// The request never got a response.
+    Network(String),
+    /// The response arrived but was not in the expected shape.
+    Parse(String),
+    /// The provider itself failed (5xx, or Anthropic's 529 "overloaded").
+    Upstream {
+        status: u16,
+        message: String,
+    },
+    Other(String),
+}
+
+impl ProviderError {
+    /// Short machine-readable tag for the frontend.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProviderError::MissingSetting(_) => "missing_setting",
+            ProviderError::Auth(_) => "auth",
+            ProviderError::RateLimited { .. } => "rate_limited",
+            ProviderError::Network(_) => "network",
+            ProviderError::Parse(_) => "parse",
+            ProviderError::Upstream { .. } => "upstream",
+            ProviderError::Other(_) => "other",
+        }
+    }
+
+    /// Classify a non-success HTTP status.
+    pub fn from_status(status: u16, retry_after_secs: Option<u64>, body: String) -> Self {
+        match status {
+            401 | 403 => ProviderError::Auth(body),
+            429 => ProviderError::RateLimited { retry_after_secs },
+            500..=599 => ProviderError::Upstream {
+                status,
+                message: body,
+            },
+            _ => ProviderError::Other(format!("HTTP {status}: {body}")),
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::MissingSetting(key) => write!(f, "setting `{key}` is not configured"),
+            ProviderError::Auth(msg) => write!(f, "authentication failed: {msg}"),
+            ProviderError::RateLimited {
+                retry_after_secs: Some(secs),
+            } => write!(f, "rate limited, retry after {secs}s"),
+            ProviderError::RateLimited { .. } => f.write_str("rate limited"),
+            ProviderError::Network(msg) => write!(f, "network error: {msg}"),
+            ProviderError::Parse(msg) => write!(f, "unexpected response: {msg}"),
+            ProviderError::Upstream { status, message } => {
+                write!(f, "provider error {status}: {message}")
+            }
+            ProviderError::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<String> for ProviderError {
+    fn from(msg: String) -> Self {
+        ProviderError::Other(msg)
+    }
+}
+
+/// Pass a successful response through; turn anything else into the matching
+/// [`ProviderError`], keeping the body for the error message.
+pub async fn check_response(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, ProviderError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after_secs = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let body = response.text().await.unwrap_or_default();
+    Err(ProviderError::from_status(
+        status.as_u16(),
+        retry_after_secs,
+        body,
+    ))
+}
+
+/// What a successful fetch produced. Days in the requested range that the
+/// provider could not return (after retries) are listed in `missing_days`
+/// instead of being reported as zero usage.
+#[derive(Debug, Default)]
+pub struct FetchResult {
+    pub snapshots: Vec<UsageSnapshot>,
+    /// The hour-level usage `snapshots` were rolled up from.
+    pub hourly: Vec<HourlyUsage>,
+    /// Per-session usage, from providers that know about sessions.
+    pub sessions: Vec<SessionUsage>,
+    pub missing_days: Vec<NaiveDate>,
+    /// Records skipped because they repeat usage already counted in this
+    /// fetch.
+    pub duplicates_dropped: u64,
+    /// Parts of the fetch that failed without failing it, e.g. an optional
+    /// report or an unreadable log.
+    pub warnings: Vec<String>,
+}
+
+/// Bucket sizes accepted by the Anthropic and OpenAI usage reports.
+#[derive(Debug, Clone, Copy)]
+pub enum BucketWidth {
+    Day,
+    Hour,
+}
+
+impl BucketWidth {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BucketWidth::Day => "1d",
+            BucketWidth::Hour => "1h",
+        }
+    }
+
+    /// Most buckets either API returns per page at this width.
+    pub fn page_limit(self) -> &'static str {
+        match self {
+            BucketWidth::Day => "31",
+            BucketWidth::Hour => "168",
+        }
+    }
+}
+
+/// Usage accumulated per `(key, model, kind)`, where `key` is a date or an
+/// hour. Each snapshot's `date` is always the day.
+pub type UsageAcc = HashMap<(String, String, UsageKind), UsageSnapshot>;
+
+/// Billed USD per `(date, model, kind)` from a provider's cost report.
+pub type Billed = HashMap<(String, String, UsageKind), f64>;
+
+/// Attach billed amounts to the daily rows they cover. An amount whose kind
+/// has no usage row goes to another row of the same model, regular usage
+/// first, or to a new row made by `empty`. A bill covers all of a model's
+/// usage that day, so the model's rows without an amount of their own are
+/// billed at zero rather than keeping their estimate and counting it twice.
+pub fn attach_billed(
+    daily: &mut UsageAcc,
+    billed: Billed,
+    empty: impl Fn(&str, String, UsageKind) -> UsageSnapshot,
+) {
+    for ((date, model, kind), usd) in billed {
+        let kind = if daily.contains_key(&(date.clone(), model.clone(), kind)) {
+            kind
+        } else {
+            daily
+                .keys()
+                .filter(|(d, m, _)| *d == date && *m == model)
+                .map(|(_, _, kind)| *kind)
+                .min_by_key(|kind| *kind != UsageKind::Text)
+                .unwrap_or(kind)
+        };
+        let entry = daily
+            .entry((date.clone(), model.clone(), kind))
+            .or_insert_with(|| empty(&date, model, kind));
+        *entry.billed_cost_usd.get_or_insert(0.0) += usd;
+    }
+
+    let billed_models: HashSet<(String, String)> = daily
+        .iter()
+        .filter(|(_, snapshot)| snapshot.billed_cost_usd.is_some())
+        .map(|((date, model, _), _)| (date.clone(), model.clone()))
+        .collect();
+    for ((date, model, _), snapshot) in daily.iter_mut() {
+        if billed_models.contains(&(date.clone(), model.clone())) {
+            snapshot.billed_cost_usd.get_or_insert(0.0);
+        }
+    }
+}
+
+/// `YYYY-MM-DDTHH:00:00Z` for any RFC 3339 timestamp in UTC.
+pub fn hour_of(timestamp: &str) -> Option<String> {
+    timestamp.get(..13).map(|h| format!("{h}:00:00Z"))
+}
+
+/// Turn per-hour accumulators into priced hourly rows and their per-day sums,
+/// with each hour counted towards the day it falls on in `tz`. The daily
+/// totals are left unpriced so billed costs can be attached first.
+pub fn roll_up(hourly: UsageAcc, tz: Tz) -> (Vec<HourlyUsage>, UsageAcc) {
+    let mut rows = Vec::with_capacity(hourly.len());
+    let mut daily = UsageAcc::new();
+    for ((hour, model, kind), mut snapshot) in hourly {
+        if let Ok(start) = DateTime::parse_from_rfc3339(&hour) {
+            snapshot.date = timezone::local_date(start.to_utc(), tz)
+                .format("%Y-%m-%d")
+                .to_string();
+        }
+        match daily.entry((snapshot.date.clone(), model, kind)) {
+            Entry::Occupied(mut day) => day.get_mut().add_usage(&snapshot),
+            Entry::Vacant(day) => {
+                day.insert(snapshot.clone());
+            }
+        }
+        let mut row = HourlyUsage::new(hour, &snapshot);
+        pricing::price_hourly(&mut row);
+        rows.push(row);
+    }
+    (rows, daily)
+}
+
+/// Drop the days outside `range` from rolled-up `daily` rows. Hour buckets
+/// start on whole UTC hours, so in a half-hour zone the first one begins
+/// before the range and is counted towards the day before it, where that
+/// one hour would replace the day's stored total.
+pub fn keep_days(daily: &mut UsageAcc, range: DateRange) {
+    daily.retain(|(date, _, _), _| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok_and(|day| range.contains(day))
+    });
+}
+
+/// A source of usage data. Implement this in a new module under `api/` and
+/// add it to [`PROVIDERS`] to make the poller and the UI pick it up.
+#[async_trait]
+pub trait UsageProvider: Send + Sync {
+    /// Stable identifier stored in `usage_snapshots.provider`.
+    fn id(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    /// Setting keys that must be non-empty before the poller calls `fetch`.
+    fn required_settings(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    async fn fetch(
+        &self,
+        ctx: &FetchContext,
+        range: DateRange,
+    ) -> Result<FetchResult, ProviderError>;
+}
+
+/// Every provider the app knows about, in display order.
+pub static PROVIDERS: &[&dyn UsageProvider] = &[
+    &anthropic::AnthropicProvider,
+    &openai::OpenAiProvider,
+    &claude_code::ClaudeCodeProvider,
+];
+
+/// Serializable description of a provider, for the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: &'static str,
+    pub display_name: &'static str,
+    pub required_settings: &'static [&'static str],
+}
+
+pub fn provider_infos() -> Vec<ProviderInfo> {
+    PROVIDERS
+        .iter()
+        .map(|p| ProviderInfo {
+            id: p.id(),
+            display_name: p.display_name(),
+            required_settings: p.required_settings(),
+        })
+        .collect()
+}
+
+/// Health of one provider as seen by the poller.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProviderStatus {
+    pub id: &'static str,
+    pub last_attempt: Option<String>,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+    /// [`ProviderError::kind`] of `last_error`.
+    pub last_error_kind: Option<&'static str>,
+    pub consecutive_failures: u32,
+    /// Days the last successful fetch could not cover.
+    pub missing_days: Vec<NaiveDate>,
+    /// Duplicate records the last successful fetch left out.
+    pub duplicates_dropped: u64,
+    /// What the last successful fetch could not read.
+    pub warnings: Vec<String>,
+}
+
+impl ProviderStatus {
+    pub fn new(id: &'static str) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_success(&mut self, result: &FetchResult) {
+        let now = Utc::now().to_rfc3339();
+        self.last_attempt = Some(now.clone());
+        self.last_success = Some(now);
+        self.consecutive_failures = 0;
+        self.missing_days = result.missing_days.clone();
+        self.duplicates_dropped = result.duplicates_dropped;
+        self.warnings = result.warnings.clone();
+    }
+
+    /// Note a failure. The last error is kept after a later success, so the
+    /// UI can still explain a recent blip.
+    pub fn record_failure(&mut self, error: &ProviderError) {
+        self.last_attempt = Some(Utc::now().to_rfc3339());
+        self.last_error = Some(error.to_string());
+        self.last_error_kind = Some(error.kind());
+        self.consecutive_failures += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::storage::Unit;
+
+    fn tokens(input_tokens: i64) -> UsageSnapshot {
+        UsageSnapshot {
+            id: None,
+            provider: "anthropic".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            date: String::new(),
+            input_tokens,
+            output_tokens: 0,
+            cache_tokens: 0,
+            cache_creation_5m_tokens: 0,
+            cache_creation_1h_tokens: 0,
+            cost_usd: 0.0,
+            estimated_cost_usd: 0.0,
+            billed_cost_usd: None,
+            usage_kind: UsageKind::Text,
+            unit: Unit::Tokens,
+            quantity: 0.0,
+            fetched_at: String::new(),
+            unpriced: false,
+        }
+    }
+
+    /// Roll one token logged in each of `hours` up into `tz` days, returning
+    /// the tokens per day.
+    fn days(hours: &[&str], tz: &str) -> BTreeMap<String, i64> {
+        let acc: UsageAcc = hours
+            .iter()
+            .map(|hour| {
+                let model = "claude-sonnet-4-5".to_string();
+                ((hour.to_string(), model, UsageKind::Text), tokens(1))
+            })
+            .collect();
+        let (rows, daily) = roll_up(acc, tz.parse().unwrap());
+        assert_eq!(rows.len(), hours.len());
+        assert!(rows.iter().all(|row| row.cost_usd > 0.0));
+        daily
+            .into_iter()
+            .map(|((date, _, _), snapshot)| (date, snapshot.input_tokens))
+            .collect()
+    }
+
+    fn expect(days: &[(&str, i64)]) -> BTreeMap<String, i64> {
+        days.iter().map(|&(d, n)| (d.to_string(), n)).collect()
+    }
+
+    #[test]
+    fn rolls_hours_up_across_dst_changes() {
+        let hours = [
+            // Midnight is 08:00Z before the spring change and 07:00Z after it
+            "2025-03-09T07:00:00Z",
+            "2025-03-09T08:00:00Z",
+            "2025-03-10T06:00:00Z",
+            "2025-03-10T07:00:00Z",
+            // and back to 08:00Z after the autumn one
+            "2025-11-02T07:00:00Z",
+            "2025-11-03T07:00:00Z",
+            "2025-11-03T08:00:00Z",
+        ];
+        assert_eq!(
+            days(&hours, "America/Los_Angeles"),
+            expect(&[
+                ("2025-03-08", 1),
+                ("2025-03-09", 2),
+                ("2025-03-10", 1),
+                ("2025-11-02", 2),
+                ("2025-11-03", 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn rolls_hours_up_in_zones_off_the_hour() {
+        // 23:30 and 00:30 in Kolkata, 23:45 and 00:45 in Kathmandu
+        let hours = ["2025-03-01T18:00:00Z", "2025-03-01T19:00:00Z"];
+        let split = expect(&[("2025-03-01", 1), ("2025-03-02", 1)]);
+        assert_eq!(days(&hours, "Asia/Kolkata"), split);
+        assert_eq!(days(&hours, "Asia/Kathmandu"), split);
+        assert_eq!(days(&hours, "UTC"), expect(&[("2025-03-01", 2)]));
+    }
+
+    #[test]
+    fn ranges_span_short_and_long_days() {
+        let la = "America/Los_Angeles".parse().unwrap();
+        let one_day = |m, d| {
+            let day = NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+            DateRange {
+                start: day,
+                end: day,
+            }
+            .instants(la)
+        };
+        let (start, end) = one_day(3, 9);
+        assert_eq!(start.to_rfc3339(), "2025-03-09T08:00:00+00:00");
+        assert_eq!(end - start, Duration::hours(23));
+        let (start, end) = one_day(11, 2);
+        assert_eq!(end - start, Duration::hours(25));
+    }
+
+    #[test]
+    fn bills_every_kind_of_a_model_once() {
+        let key = |model: &str, kind| ("2025-03-01".to_string(), model.to_string(), kind);
+        let daily: UsageAcc = [
+            (key("gpt-4o", UsageKind::Text), tokens(1_000)),
+            (key("gpt-4o", UsageKind::Batch), tokens(1_000)),
+        ]
+        .into();
+        let empty = |date: &str, model: String, kind| UsageSnapshot {
+            date: date.to_string(),
+            model,
+            usage_kind: kind,
+            ..tokens(0)
+        };
+
+        // Split by kind, as the bill itself is
+        let mut split = daily.clone();
+        let billed: Billed = [
+            (key("gpt-4o", UsageKind::Text), 2.0),
+            (key("gpt-4o", UsageKind::Batch), 0.5),
+        ]
+        .into();
+        attach_billed(&mut split, billed, empty);
+        let billed_of = |kind| split[&key("gpt-4o", kind)].billed_cost_usd;
+        assert_eq!(billed_of(UsageKind::Text), Some(2.0));
+        assert_eq!(billed_of(UsageKind::Batch), Some(0.5));
+
+        // One amount for the whole model covers its batch usage too
+        let mut combined = daily;
+        let billed: Billed = [
+            (key("gpt-4o", UsageKind::Text), 2.5),
+            (key("web_search", UsageKind::Text), 0.1),
+        ]
+        .into();
+        attach_billed(&mut combined, billed, empty);
+        let billed_of = |model, kind| combined[&key(model, kind)].billed_cost_usd;
+        assert_eq!(billed_of("gpt-4o", UsageKind::Text), Some(2.5));
+        assert_eq!(billed_of("gpt-4o", UsageKind::Batch), Some(0.0));
+        assert_eq!(billed_of("web_search", UsageKind::Text), Some(0.1));
+    }
+}
@@ -1,105 +1,407 @@
-use chrono::{Duration, Utc};
-use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
 use serde_json::Value;
 
-use crate::storage::UsageSnapshot;
-
-/// Pricing per 1M tokens (prompt / completion) — best-effort approximations.
-fn model_price(model: &str) -> (f64, f64) {
-    if model.contains("gpt-4o-mini") {
-        (0.15, 0.60)
-    } else if model.contains("gpt-4o") {
-        (2.50, 10.0)
-    } else if model.contains("gpt-4-turbo") || model.contains("gpt-4-1106") {
-        (10.0, 30.0)
-    } else if model.contains("gpt-4") {
-        (30.0, 60.0)
-    } else if model.contains("gpt-3.5") {
-        (0.50, 1.50)
-    } else if model.contains("o1-mini") {
-        (3.0, 12.0)
-    } else if model.contains("o1") {
-        (15.0, 60.0)
-    } else {
-        (2.50, 10.0) // default gpt-4o pricing
+use super::{
+    attach_billed, http, keep_days, roll_up, Billed, BucketWidth, DateRange, FetchContext,
+    FetchResult, ProviderError, UsageAcc, UsageProvider,
+};
+use crate::pricing;
+use crate::storage::{UsageKind, UsageSnapshot};
+use crate::timezone;
+
+pub struct OpenAiProvider;
+
+#[async_trait]
+impl UsageProvider for OpenAiProvider {
+    fn id(&self) -> &'static str {
+        "openai"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn required_settings(&self) -> &'static [&'static str] {
+        &["openai_key"]
+    }
+
+    async fn fetch(
+        &self,
+        ctx: &FetchContext,
+        range: DateRange,
+    ) -> Result<FetchResult, ProviderError> {
+        let api_key = ctx.require("openai_key")?;
+        fetch_openai_usage(api_key, range, ctx.tz).await
     }
 }
 
-pub async fn fetch_openai_usage(api_key: &str) -> Result<Vec<UsageSnapshot>, String> {
-    if api_key.is_empty() {
-        return Ok(vec![]);
+const API_BASE: &str = "https://api.openai.com/v1/organization";
+
+/// Pages to follow before giving up on a single report. At 168 hourly buckets
+/// per page this covers a year.
+const MAX_PAGES: usize = 60;
+
+/// One page of the organization Usage or Costs API; both return time buckets
+/// with per-group `results`, paginated by `next_page`.
+#[derive(Debug, Deserialize)]
+struct ReportPage {
+    #[serde(default)]
+    data: Vec<ReportBucket>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportBucket {
+    /// Unix seconds, UTC.
+    start_time: i64,
+    #[serde(default)]
+    results: Vec<Value>,
+}
+
+impl ReportBucket {
+    fn start(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.start_time, 0)
     }
+}
 
-    let client = Client::new();
-    let fetched_at = Utc::now().to_rfc3339();
-    let mut snapshots: std::collections::HashMap<(String, String), UsageSnapshot> =
-        std::collections::HashMap::new();
+struct Report {
+    buckets: Vec<ReportBucket>,
+    /// Set when `MAX_PAGES` was reached with pages still remaining.
+    truncated: bool,
+}
 
-    // Fetch 30 days of data (one request per day)
-    for days_ago in 0..30i64 {
-        let date = (Utc::now() - Duration::days(days_ago))
-            .format("%Y-%m-%d")
-            .to_string();
+/// Fetch every page of `endpoint` under `API_BASE` for the days of `range`
+/// as they fall in `tz`, in `width` buckets.
+async fn fetch_report(
+    api_key: &str,
+    endpoint: &str,
+    group_by: &[&str],
+    width: BucketWidth,
+    range: DateRange,
+    tz: Tz,
+) -> Result<Report, ProviderError> {
+    let (start, end) = range.instants(tz);
+    let start_time = start.timestamp().to_string();
+    let end_time = end.timestamp().to_string();
+
+    let mut query = vec![
+        ("start_time", start_time.as_str()),
+        ("end_time", end_time.as_str()),
+        ("bucket_width", width.as_str()),
+        ("limit", width.page_limit()),
+    ];
+    query.extend(group_by.iter().map(|g| ("group_by", *g)));
+    let request = http::client()
+        .get(format!("{API_BASE}/{endpoint}"))
+        .header("Authorization", format!("Bearer {api_key}"))
+        .query(&query);
+    fetch_pages(request, endpoint).await
+}
+
+/// Follow `next_page` from `request` for up to `MAX_PAGES` pages.
+async fn fetch_pages(request: RequestBuilder, endpoint: &str) -> Result<Report, ProviderError> {
+    let mut buckets = Vec::new();
+    let mut page: Option<String> = None;
 
-        let response = client
-            .get("https://api.openai.com/v1/usage")
-            .header("Authorization", format!("Bearer {api_key}"))
-            .query(&[("date", date.as_str())])
-            .send()
-            .await
-            .map_err(|e| format!("OpenAI request failed: {e}"))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            // Non-fatal: skip this day
-            continue;
+    for _ in 0..MAX_PAGES {
+        let mut this_page = request
+            .try_clone()
+            .ok_or_else(|| ProviderError::Other("request cannot be repeated".to_string()))?;
+        if let Some(token) = &page {
+            this_page = this_page.query(&[("page", token)]);
         }
+        let body: ReportPage = serde_json::from_value(http::get_json(this_page).await?)
+            .map_err(|e| ProviderError::Parse(format!("OpenAI {endpoint}: {e}")))?;
+        buckets.extend(body.data);
 
-        let body: Value = response
-            .json()
-            .await
-            .map_err(|e| format!("OpenAI parse error: {e}"))?;
-
-        if let Some(data) = body.get("data").and_then(|d| d.as_array()) {
-            for item in data {
-                let model = item
-                    .get("snapshot_id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("unknown")
-                    .to_string();
-
-                let input_tokens = item
-                    .get("n_context_tokens_total")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(0);
-                let output_tokens = item
-                    .get("n_generated_tokens_total")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(0);
-
-                let (inp_price, out_price) = model_price(&model);
-                let cost_usd =
-                    (input_tokens as f64 * inp_price + output_tokens as f64 * out_price)
-                        / 1_000_000.0;
-
-                let key = (model.clone(), date.clone());
-                let entry = snapshots.entry(key).or_insert(UsageSnapshot {
-                    id: None,
-                    provider: "openai".to_string(),
-                    model: model.clone(),
-                    date: date.clone(),
-                    input_tokens: 0,
-                    output_tokens: 0,
-                    cache_tokens: 0,
-                    cost_usd: 0.0,
-                    fetched_at: fetched_at.clone(),
-                });
-                entry.input_tokens += input_tokens;
-                entry.output_tokens += output_tokens;
-                entry.cost_usd += cost_usd;
+        match body.next_page {
+            Some(next) if body.has_more => page = Some(next),
+            _ => {
+                return Ok(Report {
+                    buckets,
+                    truncated: false,
+                })
             }
         }
     }
 
-    Ok(snapshots.into_values().collect())
+    Ok(Report {
+        buckets,
+        truncated: true,
+    })
+}
+
+/// Usage endpoints under `API_BASE`, the kind each reports, and how to group
+/// it. Completions are split by `batch` so batch requests can be priced at
+/// the discount; vector stores can only be grouped by project, so they are
+/// summed.
+#[rustfmt::skip]
+const USAGE_ENDPOINTS: &[(&str, UsageKind, &[&str])] = &[
+    ("usage/completions",          UsageKind::Text,               &["model", "batch"]),
+    ("usage/embeddings",           UsageKind::Embeddings,         &["model"]),
+    ("usage/moderations",          UsageKind::Moderation,         &["model"]),
+    ("usage/images",               UsageKind::Images,             &["model"]),
+    ("usage/audio_speeches",       UsageKind::AudioSpeech,        &["model"]),
+    ("usage/audio_transcriptions", UsageKind::AudioTranscription, &["model"]),
+    ("usage/vector_stores",        UsageKind::VectorStore,        &[]),
+];
+
+/// Model name recorded for vector store usage, which has no model.
+const VECTOR_STORE_MODEL: &str = "vector-store";
+
+pub async fn fetch_openai_usage(
+    api_key: &str,
+    range: DateRange,
+    tz: Tz,
+) -> Result<FetchResult, ProviderError> {
+    let mut acc = UsageAcc::new();
+    let mut missing_days = Vec::new();
+    let mut warnings = Vec::new();
+
+    for &(endpoint, kind, group_by) in USAGE_ENDPOINTS {
+        let fetched = fetch_report(api_key, endpoint, group_by, BucketWidth::Hour, range, tz).await;
+        let report = match fetched {
+            Ok(report) => report,
+            // Completions are the bulk of most bills, so their failure fails
+            // the fetch; the other kinds are skipped, and their days reported
+            // as missing so the watermark stays put until they are fetched.
+            Err(e) if kind != UsageKind::Text && !matches!(e, ProviderError::Auth(_)) => {
+                warnings.push(format!("{endpoint} unavailable: {e}"));
+                missing_days.extend(range.days());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut covered: HashSet<NaiveDate> = HashSet::new();
+        for bucket in &report.buckets {
+            let Some(start) = bucket.start() else { continue };
+            let hour = start.format("%Y-%m-%dT%H:00:00Z").to_string();
+            for result in &bucket.results {
+                add_result(&mut acc, &hour, kind, result);
+            }
+            covered.insert(timezone::local_date(start, tz));
+        }
+
+        // Pages run oldest first, so hitting the cap leaves the newest days
+        // unfetched; report those rather than showing them as zero.
+        if report.truncated {
+            missing_days.extend(range.days().filter(|d| !covered.contains(d)));
+        }
+    }
+    missing_days.sort();
+    missing_days.dedup();
+    let (hourly, mut daily) = roll_up(acc, tz);
+
+    // Billed amounts need a key with access to the Costs API; without them
+    // we still have the estimates. Line items only name the model, so an
+    // amount goes to whichever kind that model was used for.
+    match fetch_openai_costs(api_key, range, tz).await {
+        Ok(billed) => attach_billed(&mut daily, billed, empty_snapshot),
+        Err(e) => warnings.push(format!("Costs unavailable: {e}")),
+    }
+
+    keep_days(&mut daily, range);
+    let mut snapshots: Vec<UsageSnapshot> = daily.into_values().collect();
+    snapshots.iter_mut().for_each(pricing::price_snapshot);
+    Ok(FetchResult {
+        snapshots,
+        hourly,
+        missing_days,
+        warnings,
+        ..Default::default()
+    })
+}
+
+/// Billed USD per `(date, model, kind)`. Cost line items look like
+/// `"gpt-4o-2024-08-06, input"`; everything before the comma is the model,
+/// or the name of a non-model charge. Batch API charges mention the batch
+/// after it and are keyed as `Batch`.
+pub async fn fetch_openai_costs(
+    api_key: &str,
+    range: DateRange,
+    tz: Tz,
+) -> Result<Billed, ProviderError> {
+    // Costs only come in daily buckets
+    let report = fetch_report(
+        api_key,
+        "costs",
+        &["line_item"],
+        BucketWidth::Day,
+        range,
+        tz,
+    )
+    .await?;
+    Ok(billed_amounts(&report, tz))
+}
+
+fn billed_amounts(report: &Report, tz: Tz) -> Billed {
+    let mut billed = HashMap::new();
+    for bucket in &report.buckets {
+        let Some(start) = bucket.start() else { continue };
+        let date = timezone::bucket_date(start, tz)
+            .format("%Y-%m-%d")
+            .to_string();
+        for result in &bucket.results {
+            let Some(usd) = result
+                .get("amount")
+                .and_then(|a| a.get("value"))
+                .and_then(|v| v.as_f64())
+            else {
+                continue;
+            };
+            let item = result
+                .get("line_item")
+                .and_then(|v| v.as_str())
+                .unwrap_or("other");
+            let (model, charge) = item.split_once(", ").unwrap_or((item, ""));
+            let kind = if charge.contains("batch") {
+                UsageKind::Batch
+            } else {
+                UsageKind::Text
+            };
+            *billed
+                .entry((date.clone(), model.to_string(), kind))
+                .or_insert(0.0) += usd;
+        }
+    }
+    billed
+}
+
+fn empty_snapshot(date: &str, model: String, kind: UsageKind) -> UsageSnapshot {
+    UsageSnapshot {
+        id: None,
+        provider: "openai".to_string(),
+        model,
+        date: date.to_string(),
+        input_tokens: 0,
+        output_tokens: 0,
+        cache_tokens: 0,
+        cache_creation_5m_tokens: 0,
+        cache_creation_1h_tokens: 0,
+        cost_usd: 0.0,
+        estimated_cost_usd: 0.0,
+        billed_cost_usd: None,
+        usage_kind: kind,
+        unit: kind.unit(),
+        quantity: 0.0,
+        fetched_at: Utc::now().to_rfc3339(),
+        unpriced: false,
+    }
+}
+
+/// Fold one `results` row of a `kind` endpoint into the per-(hour, model,
+/// kind) totals. Completions made through the Batch API count as `Batch`.
+fn add_result(acc: &mut UsageAcc, hour: &str, kind: UsageKind, result: &Value) {
+    let get = |key: &str| result.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+    let batch = result.get("batch").and_then(|v| v.as_bool()) == Some(true);
+    let kind = if kind == UsageKind::Text && batch {
+        UsageKind::Batch
+    } else {
+        kind
+    };
+    let model = match kind {
+        UsageKind::VectorStore => VECTOR_STORE_MODEL,
+        _ => result
+            .get("model")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown"),
+    }
+    .to_string();
+
+    let entry = acc
+        .entry((hour.to_string(), model.clone(), kind))
+        .or_insert_with(|| empty_snapshot(&hour[..10], model, kind));
+    match kind {
+        UsageKind::Text | UsageKind::Batch => {
+            // `input_tokens` includes the cached part, which is priced separately
+            let cached = get("input_cached_tokens");
+            entry.input_tokens += (get("input_tokens") - cached).max(0);
+            entry.cache_tokens += cached;
+            entry.output_tokens += get("output_tokens");
+        }
+        UsageKind::Embeddings | UsageKind::Moderation => {
+            entry.input_tokens += get("input_tokens");
+        }
+        UsageKind::Images => entry.quantity += get("images") as f64,
+        UsageKind::AudioSpeech => entry.quantity += get("characters") as f64,
+        UsageKind::AudioTranscription => entry.quantity += get("seconds") as f64,
+        // Bytes stored over a one-hour bucket, as GB-days
+        UsageKind::VectorStore => entry.quantity += get("usage_bytes") as f64 / 1e9 / 24.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::http::tests::{self as server, serve};
+
+    fn page(start: i64, next: Option<&str>) -> String {
+        server::json(
+            &json!({
+                "data": [{"start_time": start, "results": []}],
+                "has_more": next.is_some(),
+                "next_page": next,
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn follows_next_page_to_the_last_page() {
+        let (url, requests) =
+            serve(vec![page(1740787200, Some("p2")), page(1740873600, None)]).await;
+        let report = fetch_pages(http::client().get(&url), "test").await.unwrap();
+        assert_eq!(report.buckets.len(), 2);
+        assert!(!report.truncated);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("page=p2"), "{}", requests[1]);
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_pages() {
+        let (url, requests) = serve(vec![page(1740787200, Some("more"))]).await;
+        let report = fetch_pages(http::client().get(&url), "test").await.unwrap();
+        assert!(report.truncated);
+        assert_eq!(report.buckets.len(), MAX_PAGES);
+        assert_eq!(requests.lock().unwrap().len(), MAX_PAGES);
+    }
+
+    #[test]
+    fn bills_cost_line_items_by_model_and_batch() {
+        let report = Report {
+            buckets: serde_json::from_value(json!([{
+                "start_time": 1740787200,
+                "results": [
+                    {"amount": {"value": 1.5}, "line_item": "gpt-4o-2024-08-06, input"},
+                    {"amount": {"value": 0.5}, "line_item": "gpt-4o-2024-08-06, output"},
+                    {"amount": {"value": 0.25}, "line_item": "gpt-4o-2024-08-06, input (batch)"},
+                    {"amount": {"value": 0.75}, "line_item": "web search tool calls"},
+                    {"amount": {"value": 0.1}},
+                    {"line_item": "gpt-4o-mini, input"},
+                ],
+            }]))
+            .unwrap(),
+            truncated: false,
+        };
+        let key = |model: &str, kind| ("2025-03-01".to_string(), model.to_string(), kind);
+
+        let billed = billed_amounts(&report, "Asia/Kolkata".parse().unwrap());
+        assert_eq!(billed.len(), 4);
+        assert_eq!(billed[&key("gpt-4o-2024-08-06", UsageKind::Text)], 2.0);
+        assert_eq!(billed[&key("gpt-4o-2024-08-06", UsageKind::Batch)], 0.25);
+        assert_eq!(billed[&key("web search tool calls", UsageKind::Text)], 0.75);
+        assert_eq!(billed[&key("other", UsageKind::Text)], 0.1);
+    }
 }
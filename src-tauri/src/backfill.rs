@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::Mutex;
+
+use crate::api::{DateRange, FetchContext, UsageProvider, PROVIDERS};
+use crate::poller;
+use crate::storage::{BackfillJob, Storage};
+use crate::timezone;
+use crate::AppState;
+
+/// Days requested per provider call; a few pages of hourly buckets each.
+const CHUNK_DAYS: i64 = 31;
+/// Pause between chunks, on top of the HTTP client's own 429 handling, so a
+/// long import leaves rate limit headroom for the regular poll.
+const CHUNK_PAUSE: Duration = Duration::from_secs(2);
+
+/// Outcome of a finished backfill.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillSummary {
+    pub provider: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub snapshot_count: usize,
+    pub missing_days: Vec<NaiveDate>,
+}
+
+/// Import `provider_id`'s usage from `start` through `end` (clamped to the
+/// last [`poller::MAX_HISTORY_DAYS`] days), oldest chunk first. Progress is
+/// stored after every chunk, so re-running an interrupted backfill over the
+/// same range continues where it stopped.
+pub async fn run<R: Runtime>(
+    app: &AppHandle<R>,
+    provider_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<BackfillSummary, String> {
+    let provider = PROVIDERS
+        .iter()
+        .copied()
+        .find(|p| p.id() == provider_id)
+        .ok_or_else(|| format!("Unknown provider: {provider_id}"))?;
+
+    let settings = poller::read_settings(app).await;
+    let ctx = poller::fetch_context(app, &settings);
+    if !ctx.has_settings(provider.required_settings()) {
+        return Err(format!("{} is not configured", provider.display_name()));
+    }
+
+    let today = timezone::today(ctx.tz);
+    let start = start.max(today - chrono::Duration::days(poller::MAX_HISTORY_DAYS));
+    let end = end.min(today);
+    if start > end {
+        return Err(format!("Nothing to backfill between {start} and {end}"));
+    }
+
+    // One backfill per provider at a time
+    if !app
+        .state::<Mutex<AppState>>()
+        .lock()
+        .await
+        .backfills
+        .insert(provider.id())
+    {
+        return Err(format!(
+            "{} is already being backfilled",
+            provider.display_name()
+        ));
+    }
+    let result = run_chunks(app, provider, &ctx, start, end).await;
+    app.state::<Mutex<AppState>>()
+        .lock()
+        .await
+        .backfills
+        .remove(provider.id());
+
+    let _ = app.emit("usage-updated", ());
+    result
+}
+
+async fn run_chunks<R: Runtime>(
+    app: &AppHandle<R>,
+    provider: &dyn UsageProvider,
+    ctx: &FetchContext,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<BackfillSummary, String> {
+    let storage = app.state::<Storage>().inner().clone();
+
+    let pending = storage
+        .backfill_jobs()
+        .await?
+        .into_iter()
+        .find(|j| j.provider == provider.id());
+    let next = resume_from(pending.as_ref(), start, end);
+    let mut job = BackfillJob {
+        provider: provider.id().to_string(),
+        start,
+        end,
+        next,
+    };
+    storage.save_backfill_job(job.clone()).await?;
+
+    let mut summary = BackfillSummary {
+        provider: job.provider.clone(),
+        start,
+        end,
+        snapshot_count: 0,
+        missing_days: Vec::new(),
+    };
+    while job.next <= end {
+        let range = chunk(job.next, end);
+        let result = provider.fetch(ctx, range).await.map_err(|e| {
+            format!(
+                "{} backfill stopped at {}: {e}",
+                provider.display_name(),
+                range.start
+            )
+        })?;
+        summary.snapshot_count += result.snapshots.len();
+        summary.missing_days.extend(result.missing_days);
+        let stored =
+            poller::store_snapshots(app, result.snapshots, result.hourly, result.sessions).await;
+        if !stored {
+            return Err("Failed to store backfilled usage".to_string());
+        }
+
+        job.next = range.end + chrono::Duration::days(1);
+        storage.save_backfill_job(job.clone()).await?;
+        let _ = app.emit("backfill-progress", &job);
+
+        if job.next <= end {
+            tokio::time::sleep(CHUNK_PAUSE).await;
+        }
+    }
+
+    storage.delete_backfill_job(job.provider).await?;
+    Ok(summary)
+}
+
+/// The first day still to import from `start` through `end`. An earlier,
+/// interrupted run need not have had the same range: both ends are clamped
+/// to today, which moves on between runs. It is continued as long as it
+/// covered `start` and stopped before `end`.
+fn resume_from(pending: Option<&BackfillJob>, start: NaiveDate, end: NaiveDate) -> NaiveDate {
+    match pending {
+        Some(j) if j.start <= start && j.next <= end => j.next.max(start),
+        _ => start,
+    }
+}
+
+/// The chunk starting at `next`, ending no later than `end`.
+fn chunk(next: NaiveDate, end: NaiveDate) -> DateRange {
+    DateRange {
+        start: next,
+        end: (next + chrono::Duration::days(CHUNK_DAYS - 1)).min(end),
+    }
+}
+
+/// Restart every backfill that was still running when the app last quit.
+pub async fn resume_pending<R: Runtime>(app: AppHandle<R>) {
+    let jobs = match app.state::<Storage>().backfill_jobs().await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log::error!("failed to read backfill jobs: {e}");
+            return;
+        }
+    };
+    for job in jobs {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = run(&app, &job.provider, job.start, job.end).await {
+                log::error!("backfill for {} failed: {e}", job.provider);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn splits_a_range_into_month_sized_chunks() {
+        let end = day("2025-03-15");
+        let mut chunks = Vec::new();
+        let mut next = day("2025-01-01");
+        while next <= end {
+            let range = chunk(next, end);
+            chunks.push((range.start.to_string(), range.end.to_string()));
+            next = range.end + chrono::Duration::days(1);
+        }
+        assert_eq!(
+            chunks,
+            [
+                ("2025-01-01".to_string(), "2025-01-31".to_string()),
+                ("2025-02-01".to_string(), "2025-03-03".to_string()),
+                ("2025-03-04".to_string(), "2025-03-15".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resumes_an_interrupted_run_that_covers_the_range() {
+        let end = day("2025-03-12");
+        let resumed = |(job_start, job_next): (&str, &str), start: &str| {
+            let job = BackfillJob {
+                provider: "openai".to_string(),
+                start: day(job_start),
+                end: day("2025-03-10"),
+                next: day(job_next),
+            };
+            resume_from(Some(&job), day(start), end).to_string()
+        };
+        assert_eq!(resume_from(None, day("2025-01-01"), end), day("2025-01-01"));
+        let cases = [
+            (("2025-01-01", "2025-02-01"), "2025-01-01", "2025-02-01"),
+            // The range is clamped to the history window, which moved on
+            (("2025-01-01", "2025-02-01"), "2025-01-03", "2025-02-01"),
+            (("2025-01-01", "2025-01-02"), "2025-01-03", "2025-01-03"),
+            // A run over a later range, or one that already finished, starts over
+            (("2025-01-05", "2025-02-01"), "2025-01-01", "2025-01-01"),
+            (("2025-01-01", "2025-03-13"), "2025-01-01", "2025-01-01"),
+        ];
+        for (job, start, expected) in cases {
+            assert_eq!(resumed(job, start), expected, "{job:?} from {start}");
+        }
+    }
+}
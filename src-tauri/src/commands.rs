@@ -1,10 +1,21 @@
 use std::collections::HashMap;
 
+use chrono::NaiveDate;
+use chrono_tz::Tz;
 use serde_json::Value;
 use tauri::{AppHandle, Manager, Runtime, State};
 use tokio::sync::Mutex;
 
-use crate::storage::INIT_SQL;
+use crate::api::claude_code::{self, LogRoot};
+use crate::api::{provider_infos, DateRange, ProviderInfo, ProviderStatus, PROVIDERS};
+use crate::backfill::{self, BackfillSummary};
+use crate::poller::{self, PollSummary};
+use crate::pricing::{self, PriceTable};
+use crate::storage::{
+    BackfillJob, CostComparison, DailyTotal, GroupBy, HourlyTotal, ModelTotal, Period,
+    PeriodSummary, ProfileTotal, ProjectTotal, SessionTotal, Storage, UsageSnapshot,
+};
+use crate::timezone;
 use crate::AppState;
 
 #[tauri::command]
@@ -27,7 +38,12 @@ pub async fn save_settings<R: Runtime>(
     let path = settings_path(&app);
     let mut map = read_settings_map(&path).await;
     for (k, v) in settings {
-        map.insert(k, Value::String(v));
+        // An emptied field clears the setting, so its default applies again
+        if v.is_empty() {
+            map.remove(&k);
+        } else {
+            map.insert(k, Value::String(v));
+        }
     }
     write_settings_map(&path, &map).await
 }
@@ -44,16 +60,192 @@ pub async fn get_settings<R: Runtime>(
         .collect())
 }
 
+/// Force an immediate poll and wait for it to finish.
 #[tauri::command]
-pub async fn trigger_refresh(state: State<'_, Mutex<AppState>>) -> Result<(), String> {
+pub async fn trigger_refresh(state: State<'_, Mutex<AppState>>) -> Result<PollSummary, String> {
+    request_poll(&state).await
+}
+
+/// Drop the fetch watermark for `provider` (or every provider) and poll, so
+/// the whole history window is fetched again. For when stored history is
+/// suspected to have drifted from what the provider reports. Claude Code
+/// logs are also read again from the start.
+#[tauri::command]
+pub async fn full_resync(
+    state: State<'_, Mutex<AppState>>,
+    storage: State<'_, Storage>,
+    provider: Option<String>,
+) -> Result<PollSummary, String> {
+    if provider.as_deref().is_none_or(|p| p == "claude_code") {
+        claude_code::reset_logs(&storage).await?;
+    }
+    storage.clear_watermarks(provider).await?;
+    request_poll(&state).await
+}
+
+async fn request_poll(state: &Mutex<AppState>) -> Result<PollSummary, String> {
     let tx = state.lock().await.refresh_tx.clone();
-    let _ = tx.try_send(());
-    Ok(())
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    tx.send(done_tx)
+        .await
+        .map_err(|_| "Poller is not running".to_string())?;
+    done_rx
+        .await
+        .map_err(|_| "Poller stopped before the refresh finished".to_string())
+}
+
+#[tauri::command]
+pub fn list_providers() -> Vec<ProviderInfo> {
+    provider_infos()
+}
+
+/// Fetch health for every provider, in display order. Providers the poller
+/// has not tried yet (e.g. no key configured) come back with empty fields.
+#[tauri::command]
+pub async fn get_provider_status(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<ProviderStatus>, String> {
+    let state = state.lock().await;
+    Ok(PROVIDERS
+        .iter()
+        .map(|p| {
+            state
+                .provider_status
+                .get(p.id())
+                .cloned()
+                .unwrap_or_else(|| ProviderStatus::new(p.id()))
+        })
+        .collect())
+}
+
+/// The effective price table, with each entry tagged as built-in or override.
+#[tauri::command]
+pub fn get_pricing_table() -> PriceTable {
+    pricing::table().as_ref().clone()
+}
+
+/// Recompute `cost_usd` for every stored snapshot from its token counts, at
+/// the prices in effect on its date. Returns the number of rows updated.
+#[tauri::command]
+pub async fn recompute_costs<R: Runtime>(app: AppHandle<R>) -> Result<usize, String> {
+    poller::recompute_stored_costs(&app).await
+}
+
+/// Stored snapshots from the last `days` days, oldest first.
+#[tauri::command]
+pub async fn get_snapshots<R: Runtime>(
+    app: AppHandle<R>,
+    storage: State<'_, Storage>,
+    days: i64,
+) -> Result<Vec<UsageSnapshot>, String> {
+    let since = (timezone::today(configured_tz(&app).await) - chrono::Duration::days(days))
+        .format("%Y-%m-%d")
+        .to_string();
+    storage.snapshots_since(since).await
+}
+
+/// Per-day cost within `range`, broken down by provider or model.
+#[tauri::command]
+pub async fn get_daily_totals(
+    storage: State<'_, Storage>,
+    range: DateRange,
+    group_by: GroupBy,
+) -> Result<Vec<DailyTotal>, String> {
+    storage.daily_totals(range, group_by).await
+}
+
+/// Per-hour cost across `date` in the configured timezone, broken down by
+/// provider or model; for spotting when a runaway job started.
+#[tauri::command]
+pub async fn get_hourly_timeline<R: Runtime>(
+    app: AppHandle<R>,
+    storage: State<'_, Storage>,
+    date: NaiveDate,
+    group_by: GroupBy,
+) -> Result<Vec<HourlyTotal>, String> {
+    let tz = configured_tz(&app).await;
+    storage.hourly_timeline(date, tz, group_by).await
 }
 
+/// Per-model totals within `range`, most expensive first.
 #[tauri::command]
-pub fn get_init_sql() -> &'static str {
-    INIT_SQL
+pub async fn get_model_totals(
+    storage: State<'_, Storage>,
+    range: DateRange,
+) -> Result<Vec<ModelTotal>, String> {
+    storage.model_totals(range).await
+}
+
+/// Rows returned by the top projects/sessions commands unless asked otherwise.
+const DEFAULT_TOP_LIMIT: usize = 10;
+
+/// Claude Code projects that cost the most within `range`.
+#[tauri::command]
+pub async fn get_top_projects(
+    storage: State<'_, Storage>,
+    range: DateRange,
+    limit: Option<usize>,
+) -> Result<Vec<ProjectTotal>, String> {
+    storage
+        .top_projects(range, limit.unwrap_or(DEFAULT_TOP_LIMIT))
+        .await
+}
+
+/// Claude Code sessions that cost the most within `range`.
+#[tauri::command]
+pub async fn get_top_sessions(
+    storage: State<'_, Storage>,
+    range: DateRange,
+    limit: Option<usize>,
+) -> Result<Vec<SessionTotal>, String> {
+    storage
+        .top_sessions(range, limit.unwrap_or(DEFAULT_TOP_LIMIT))
+        .await
+}
+
+/// Claude Code spend per profile (config directory) within `range`.
+#[tauri::command]
+pub async fn get_claude_code_profiles(
+    storage: State<'_, Storage>,
+    range: DateRange,
+) -> Result<Vec<ProfileTotal>, String> {
+    storage.profile_totals(range).await
+}
+
+/// The Claude Code config directories logs are read from, configured and
+/// discovered.
+#[tauri::command]
+pub async fn get_claude_code_roots<R: Runtime>(app: AppHandle<R>) -> Vec<LogRoot> {
+    let settings = poller::read_settings(&app).await;
+    let ctx = poller::fetch_context(&app, &settings);
+    claude_code::log_roots(&ctx.home_dir, &ctx.settings)
+}
+
+/// Per-day estimate vs. billed cost for providers that report billed amounts.
+#[tauri::command]
+pub async fn get_cost_comparison<R: Runtime>(
+    app: AppHandle<R>,
+    storage: State<'_, Storage>,
+    range: DateRange,
+) -> Result<Vec<CostComparison>, String> {
+    let tz = configured_tz(&app).await;
+    storage.cost_comparison(range, tz).await
+}
+
+/// Cost and tokens for today, the last week or the month to date, in the
+/// configured timezone.
+#[tauri::command]
+pub async fn get_period_summary<R: Runtime>(
+    app: AppHandle<R>,
+    storage: State<'_, Storage>,
+    period: Period,
+) -> Result<PeriodSummary, String> {
+    let today = timezone::today(configured_tz(&app).await);
+    storage.period_summary(period, today).await
+}
+
+async fn configured_tz<R: Runtime>(app: &AppHandle<R>) -> Tz {
+    timezone::from_settings(&read_settings_map(&settings_path(app)).await)
 }
 
 fn settings_path<R: Runtime>(app: &AppHandle<R>) -> std::path::PathBuf {
@@ -86,3 +278,22 @@ pub async fn write_settings_map(
         .await
         .map_err(|e| e.to_string())
 }
+
+/// Import `provider`'s history from `from` through `to`, up to a year back.
+/// Resolves once the whole range is stored; `backfill-progress` events report
+/// each chunk along the way.
+#[tauri::command]
+pub async fn backfill<R: Runtime>(
+    app: AppHandle<R>,
+    provider: String,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<BackfillSummary, String> {
+    backfill::run(&app, &provider, from, to).await
+}
+
+/// Backfills that have not finished yet, including ones interrupted by a quit.
+#[tauri::command]
+pub async fn get_backfill_jobs(storage: State<'_, Storage>) -> Result<Vec<BackfillJob>, String> {
+    storage.backfill_jobs().await
+}
@@ -1,50 +1,91 @@
 mod api;
+mod backfill;
 mod commands;
 mod poller;
+mod pricing;
 mod storage;
+mod timezone;
 mod tray;
+mod watcher;
 
-use tauri::Manager;
+use std::collections::{HashMap, HashSet};
+
+use tauri::{Emitter, Manager};
 use tokio::sync::Mutex;
 
 pub use storage::UsageSnapshot;
-pub use storage::INIT_SQL;
 
 // ─── Shared app state ────────────────────────────────────────────────────────
 
 pub struct AppState {
-    pub refresh_tx: tokio::sync::mpsc::Sender<()>,
+    pub refresh_tx: tokio::sync::mpsc::Sender<poller::RefreshRequest>,
+    /// Latest fetch outcome per provider id; see `get_provider_status`.
+    pub provider_status: HashMap<&'static str, api::ProviderStatus>,
+    /// Provider ids with a backfill currently running.
+    pub backfills: HashSet<&'static str>,
 }
 
 // ─── App builder ─────────────────────────────────────────────────────────────
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let (refresh_tx, _refresh_rx) = tokio::sync::mpsc::channel::<()>(4);
+    let (refresh_tx, refresh_rx) = tokio::sync::mpsc::channel::<poller::RefreshRequest>(4);
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_notification::init())
-        .plugin(tauri_plugin_sql::Builder::default().build())
+        .plugin(tauri_plugin_log::Builder::new().build())
         .manage(Mutex::new(AppState {
             refresh_tx: refresh_tx.clone(),
+            provider_status: HashMap::new(),
+            backfills: HashSet::new(),
         }))
         .invoke_handler(tauri::generate_handler![
             commands::save_setting,
             commands::save_settings,
             commands::get_settings,
             commands::trigger_refresh,
-            commands::get_init_sql,
+            commands::full_resync,
+            commands::list_providers,
+            commands::get_provider_status,
+            commands::get_pricing_table,
+            commands::recompute_costs,
+            commands::get_snapshots,
+            commands::get_daily_totals,
+            commands::get_hourly_timeline,
+            commands::get_model_totals,
+            commands::get_top_projects,
+            commands::get_top_sessions,
+            commands::get_claude_code_profiles,
+            commands::get_claude_code_roots,
+            commands::get_period_summary,
+            commands::get_cost_comparison,
+            commands::backfill,
+            commands::get_backfill_jobs,
         ])
         .setup(|app| {
             tray::setup_tray(app.handle())?;
 
+            let db_path = app.path().app_config_dir()?.join(storage::DB_FILE);
+            app.manage(storage::Storage::open(&db_path)?);
+
             let handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
                 if let Ok(data_dir) = handle.path().app_local_data_dir() {
                     let _ = tokio::fs::create_dir_all(&data_dir).await;
+                    pricing::set_table(pricing::load_table(&data_dir).await);
+                    let pricing_handle = handle.clone();
+                    tauri::async_runtime::spawn(pricing::watch_overrides(data_dir, move || {
+                        let handle = pricing_handle.clone();
+                        tauri::async_runtime::spawn(async move {
+                            let _ = poller::recompute_stored_costs(&handle).await;
+                            let _ = handle.emit("pricing-updated", ());
+                        });
+                    }));
                 }
-                poller::start_poller(handle);
+                poller::start_poller(handle.clone(), refresh_rx);
+                watcher::start_log_watcher(handle.clone());
+                backfill::resume_pending(handle).await;
             });
 
             Ok(())
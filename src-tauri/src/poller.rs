@@ -1,20 +1,26 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
+use chrono::NaiveDate;
+use serde::Serialize;
 use serde_json::Value;
 use tauri::{AppHandle, Emitter, Manager, Runtime};
 use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{mpsc, oneshot, Mutex};
 
 use crate::api::{
-    anthropic::fetch_anthropic_usage,
-    claude_code::fetch_claude_code_usage,
-    openai::fetch_openai_usage,
+    DateRange, FetchContext, FetchResult, ProviderError, ProviderStatus, UsageProvider, PROVIDERS,
 };
+use crate::storage::{HourlyUsage, Period, SessionUsage, Storage, UsageSnapshot};
+use crate::timezone;
 use crate::tray::update_tray_tooltip;
+use crate::AppState;
 
 /// Read a setting from the in-memory store (via JS bridge would be cleaner, but
 /// for now we persist settings in the app's local data dir as a JSON file so
 /// Rust can read them without going through JS).
-async fn read_settings(app: &AppHandle<impl Runtime>) -> serde_json::Map<String, Value> {
+pub async fn read_settings(app: &AppHandle<impl Runtime>) -> serde_json::Map<String, Value> {
     let path = settings_path(app);
     if let Ok(contents) = tokio::fs::read_to_string(&path).await {
         if let Ok(Value::Object(map)) = serde_json::from_str(&contents) {
@@ -31,32 +37,219 @@ fn settings_path(app: &AppHandle<impl Runtime>) -> std::path::PathBuf {
         .join("settings.json")
 }
 
-/// Persist snapshots to SQLite via the sql plugin.
-/// We use the app's local data dir to store a simple JSON file that
-/// bridges Rust ↔ JS (the SQL plugin is JS-side). Instead of duplicating
-/// the sql plugin in Rust, we emit an event with the new snapshots and
-/// let the frontend do the upsert.
-async fn emit_snapshots<R: Runtime>(app: &AppHandle<R>, snapshots: Vec<crate::storage::UsageSnapshot>) {
-    if snapshots.is_empty() {
-        return;
+/// Persist snapshots to SQLite, then let any open window know about them.
+/// Storage happens here rather than in the frontend so history keeps being
+/// recorded while the app is tray-only. `hourly` holds the hour-level rows
+/// the snapshots were rolled up from and `sessions` any per-session rows.
+/// Returns whether all of them were written.
+pub async fn store_snapshots<R: Runtime>(
+    app: &AppHandle<R>,
+    snapshots: Vec<UsageSnapshot>,
+    hourly: Vec<HourlyUsage>,
+    sessions: Vec<SessionUsage>,
+) -> bool {
+    // A day can have no daily rows left (e.g. all zero) while its hours or
+    // sessions still changed, so only skip when there is nothing at all
+    if snapshots.is_empty() && hourly.is_empty() && sessions.is_empty() {
+        return true;
+    }
+    let storage = app.state::<Storage>().inner().clone();
+    let stored = async {
+        storage.upsert_snapshots(snapshots.clone()).await?;
+        storage.upsert_hourly(hourly).await?;
+        storage.upsert_sessions(sessions).await
+    }
+    .await;
+    if let Err(e) = &stored {
+        log::error!("failed to store snapshots: {e}");
     }
     let _ = app.emit("new-snapshots", &snapshots);
+    stored.is_ok()
+}
+
+/// Re-cost every stored snapshot at the prices in effect on its date, e.g.
+/// after the price table changed. Returns how many rows were updated.
+pub async fn recompute_stored_costs<R: Runtime>(app: &AppHandle<R>) -> Result<usize, String> {
+    let storage = app.state::<Storage>().inner().clone();
+    let count = storage.reprice_all().await?;
+    refresh_tray_total(app).await;
+    let _ = app.emit("usage-updated", ());
+    Ok(count)
+}
+
+/// Show today's stored total in the tray tooltip and return it. "Today" is
+/// the current day in the configured timezone.
+async fn refresh_tray_total<R: Runtime>(app: &AppHandle<R>) -> f64 {
+    let tz = timezone::from_settings(&read_settings(app).await);
+    let storage = app.state::<Storage>().inner().clone();
+    let today_cost = match storage
+        .period_summary(Period::Today, timezone::today(tz))
+        .await
+    {
+        Ok(summary) => summary.cost_usd,
+        Err(e) => {
+            log::warn!("failed to read today's total: {e}");
+            0.0
+        }
+    };
+    update_tray_tooltip(app, &format!("Token Tracker — ${today_cost:.2} today"));
+    today_cost
 }
 
-async fn check_alerts<R: Runtime>(app: &AppHandle<R>, settings: &serde_json::Map<String, Value>) {
-    // Read today's total cost from the tray label cache file
-    let cache_path = app
+/// Fetch today's usage from one provider outside the regular poll, e.g. as
+/// soon as it writes new local logs, and update the tray and any open window.
+/// Alerts are left to the regular poll so they don't fire on every write.
+pub async fn refresh_today<R: Runtime>(app: &AppHandle<R>, provider: &dyn UsageProvider) {
+    let settings = read_settings(app).await;
+    let ctx = fetch_context(app, &settings);
+    let today = timezone::today(ctx.tz);
+    let range = DateRange {
+        start: today,
+        end: today,
+    };
+    match provider.fetch(&ctx, range).await {
+        Ok(result) => {
+            record_status(app, provider, Ok(&result)).await;
+            store_snapshots(app, result.snapshots, result.hourly, result.sessions).await;
+        }
+        Err(e) => record_status(app, provider, Err(&e)).await,
+    }
+    refresh_tray_total(app).await;
+    let _ = app.emit("usage-updated", ());
+}
+
+/// Days of history every provider is fetched for when it has no watermark,
+/// unless the `history_days` setting says otherwise.
+const DEFAULT_HISTORY_DAYS: i64 = 30;
+/// Upper bound for `history_days` and for backfills.
+pub const MAX_HISTORY_DAYS: i64 = 365;
+/// Closed days before today that are still re-fetched on every poll, since
+/// providers keep revising recent buckets for a while after the day ends.
+const SETTLE_DAYS: i64 = 2;
+
+/// The `history_days` setting, clamped to what we are willing to fetch.
+pub fn history_days(settings: &serde_json::Map<String, Value>) -> i64 {
+    settings
+        .get("history_days")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_DAYS)
+        .clamp(1, MAX_HISTORY_DAYS)
+}
+
+/// Settings, home directory and storage, as handed to every provider's `fetch`.
+pub fn fetch_context<R: Runtime>(
+    app: &AppHandle<R>,
+    settings: &serde_json::Map<String, Value>,
+) -> FetchContext {
+    // Resolve home directory for local Claude Code log parsing
+    let home_dir = app
         .path()
-        .app_local_data_dir()
-        .unwrap_or_else(|_| std::path::PathBuf::from("."))
-        .join("today_cost.json");
+        .home_dir()
+        .unwrap_or_else(|_| std::path::PathBuf::from("."));
 
-    let today_cost: f64 = if let Ok(s) = tokio::fs::read_to_string(&cache_path).await {
-        serde_json::from_str(&s).unwrap_or(0.0)
-    } else {
-        0.0
+    FetchContext {
+        settings: settings
+            .iter()
+            .filter_map(|(k, v)| v.as_str().map(|s| (k.clone(), s.to_string())))
+            .collect::<HashMap<_, _>>(),
+        home_dir,
+        tz: timezone::from_settings(settings),
+        storage: app.state::<Storage>().inner().clone(),
+    }
+}
+
+/// The days `provider` still needs: from the day after its watermark through
+/// today, never reaching back further than the history window.
+async fn fetch_range(
+    storage: &Storage,
+    provider: &dyn UsageProvider,
+    today: NaiveDate,
+    history_days: i64,
+) -> DateRange {
+    let watermark = match storage.watermark(provider.id()).await {
+        Ok(day) => day,
+        Err(e) => {
+            log::warn!("failed to read {} watermark: {e}", provider.display_name());
+            None
+        }
     };
+    range_after(watermark, today, history_days)
+}
 
+/// The days after `watermark` through `today`, or the whole history window
+/// without one.
+fn range_after(watermark: Option<NaiveDate>, today: NaiveDate, history_days: i64) -> DateRange {
+    let history_start = today - chrono::Duration::days(history_days);
+    let start = watermark.map_or(history_start, |day| {
+        (day + chrono::Duration::days(1)).max(history_start)
+    });
+    DateRange {
+        start: start.min(today),
+        end: today,
+    }
+}
+
+/// Move the watermark up to the last settled day of `range`, stopping short
+/// of the first day the provider could not return.
+async fn advance_watermark(
+    storage: &Storage,
+    provider: &dyn UsageProvider,
+    range: DateRange,
+    missing_days: &[NaiveDate],
+    today: NaiveDate,
+) {
+    let Some(through) = settled_through(range, missing_days, today) else {
+        return;
+    };
+    if let Err(e) = storage.set_watermark(provider.id(), through).await {
+        log::warn!("failed to store {} watermark: {e}", provider.display_name());
+    }
+}
+
+/// The last settled day of `range` before the first missing day, or `None`
+/// if there is no such day.
+fn settled_through(
+    range: DateRange,
+    missing_days: &[NaiveDate],
+    today: NaiveDate,
+) -> Option<NaiveDate> {
+    let settled = today - chrono::Duration::days(SETTLE_DAYS + 1);
+    let through = match missing_days.iter().min() {
+        Some(first) => settled.min(*first - chrono::Duration::days(1)),
+        None => settled,
+    };
+    (through >= range.start).then_some(through)
+}
+
+/// Update the provider's entry in `AppState` and broadcast it as a
+/// `provider-status` event.
+async fn record_status<R: Runtime>(
+    app: &AppHandle<R>,
+    provider: &dyn UsageProvider,
+    result: Result<&FetchResult, &ProviderError>,
+) {
+    let status = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().await;
+        let status = state
+            .provider_status
+            .entry(provider.id())
+            .or_insert_with(|| ProviderStatus::new(provider.id()));
+        match result {
+            Ok(fetched) => status.record_success(fetched),
+            Err(e) => status.record_failure(e),
+        }
+        status.clone()
+    };
+    let _ = app.emit("provider-status", &status);
+}
+
+async fn check_alerts<R: Runtime>(
+    app: &AppHandle<R>,
+    settings: &serde_json::Map<String, Value>,
+    today_cost: f64,
+) {
     if let Some(threshold_str) = settings.get("alert_daily_usd").and_then(|v| v.as_str()) {
         if let Ok(threshold) = threshold_str.parse::<f64>() {
             if threshold > 0.0 && today_cost >= threshold {
@@ -73,8 +266,23 @@ async fn check_alerts<R: Runtime>(app: &AppHandle<R>, settings: &serde_json::Map
     }
 }
 
-pub fn start_poller<R: Runtime + 'static>(app: AppHandle<R>) {
+/// Outcome of a single poll, reported back to whoever forced it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PollSummary {
+    pub snapshot_count: usize,
+    pub today_cost_usd: f64,
+    pub finished_at: String,
+}
+
+/// A forced-poll request; the poller answers on the oneshot once it has run.
+pub type RefreshRequest = oneshot::Sender<PollSummary>;
+
+pub fn start_poller<R: Runtime + 'static>(
+    app: AppHandle<R>,
+    mut refresh_rx: mpsc::Receiver<RefreshRequest>,
+) {
     tauri::async_runtime::spawn(async move {
+        let mut waiters: Vec<RefreshRequest> = Vec::new();
         loop {
             let settings = read_settings(&app).await;
 
@@ -84,71 +292,132 @@ pub fn start_poller<R: Runtime + 'static>(app: AppHandle<R>) {
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(300);
 
-            let anthropic_key = settings
-                .get("anthropic_key")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            let openai_key = settings
-                .get("openai_key")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            // Resolve home directory for local Claude Code log parsing
-            let home_dir = app
-                .path()
-                .home_dir()
-                .unwrap_or_else(|_| std::path::PathBuf::from("."));
-
-            // Fetch all providers concurrently
-            let (anthropic_result, openai_result, claude_code_result) = tokio::join!(
-                fetch_anthropic_usage(&anthropic_key),
-                fetch_openai_usage(&openai_key),
-                fetch_claude_code_usage(home_dir),
-            );
-
-            let mut all_snapshots = Vec::new();
-            if let Ok(snaps) = anthropic_result {
-                all_snapshots.extend(snaps);
+            let summary = poll_once(&app, &settings).await;
+            for waiter in waiters.drain(..) {
+                let _ = waiter.send(summary.clone());
             }
-            if let Ok(snaps) = openai_result {
-                all_snapshots.extend(snaps);
+
+            // Sleep until the next tick or a manual refresh, whichever comes
+            // first. Requests that pile up while we wait are coalesced into
+            // a single poll and all answered with its summary.
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(poll_secs)) => {}
+                Some(request) = refresh_rx.recv() => {
+                    waiters.push(request);
+                    while let Ok(request) = refresh_rx.try_recv() {
+                        waiters.push(request);
+                    }
+                }
             }
-            if let Ok(snaps) = claude_code_result {
-                all_snapshots.extend(snaps);
+        }
+    });
+}
+
+async fn poll_once<R: Runtime>(
+    app: &AppHandle<R>,
+    settings: &serde_json::Map<String, Value>,
+) -> PollSummary {
+    let ctx = Arc::new(fetch_context(app, settings));
+    let history_days = history_days(settings);
+    let storage = app.state::<Storage>().inner().clone();
+    let today = timezone::today(ctx.tz);
+
+    // Fetch every configured provider concurrently, each only from its
+    // watermark onwards
+    let mut tasks = tokio::task::JoinSet::new();
+    for provider in PROVIDERS.iter().copied() {
+        if !ctx.has_settings(provider.required_settings()) {
+            continue;
+        }
+        let range = fetch_range(&storage, provider, today, history_days).await;
+        let ctx = ctx.clone();
+        tasks.spawn(async move { (provider, range, provider.fetch(&ctx, range).await) });
+    }
+
+    let mut all_snapshots = Vec::new();
+    let mut all_hourly = Vec::new();
+    let mut all_sessions = Vec::new();
+    let mut pending_watermarks = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((provider, range, Ok(result))) => {
+                record_status(app, provider, Ok(&result)).await;
+                all_snapshots.extend(result.snapshots);
+                all_hourly.extend(result.hourly);
+                all_sessions.extend(result.sessions);
+                pending_watermarks.push((provider, range, result.missing_days));
             }
+            Ok((provider, _, Err(e))) => record_status(app, provider, Err(&e)).await,
+            Err(e) => log::error!("provider task panicked: {e}"),
+        }
+    }
 
-            // Compute today's total cost for tray + alert
-            let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
-            let today_cost: f64 = all_snapshots
-                .iter()
-                .filter(|s| s.date == today)
-                .map(|s| s.cost_usd)
-                .sum();
+    let snapshot_count = all_snapshots.len();
 
-            // Cache today's cost for alert checking
-            let cache_path = app
-                .path()
-                .app_local_data_dir()
-                .unwrap_or_else(|_| std::path::PathBuf::from("."))
-                .join("today_cost.json");
-            let _ = tokio::fs::write(&cache_path, today_cost.to_string()).await;
+    // Only advance watermarks once the rows they vouch for are stored
+    if store_snapshots(app, all_snapshots, all_hourly, all_sessions).await {
+        for (provider, range, missing_days) in pending_watermarks {
+            advance_watermark(&storage, provider, range, &missing_days, today).await;
+        }
+    }
 
-            // Update tray tooltip
-            update_tray_tooltip(&app, &format!("Token Tracker — ${today_cost:.2} today"));
+    // Tray and alerts read today's total back from storage, so they agree
+    // with what the dashboard shows
+    let today_cost = refresh_tray_total(app).await;
+    check_alerts(app, settings, today_cost).await;
 
-            // Push snapshots to frontend for DB upsert
-            emit_snapshots(&app, all_snapshots).await;
+    // Signal frontend to refresh its data view
+    let _ = app.emit("usage-updated", ());
 
-            // Check spending alerts
-            check_alerts(&app, &settings).await;
+    PollSummary {
+        snapshot_count,
+        today_cost_usd: today_cost,
+        finished_at: chrono::Utc::now().to_rfc3339(),
+    }
+}
 
-            // Signal frontend to refresh its data view
-            let _ = app.emit("usage-updated", ());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            tokio::time::sleep(Duration::from_secs(poll_secs)).await;
-        }
-    });
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn fetches_from_the_day_after_the_watermark() {
+        let today = day("2025-03-10");
+        let start = |watermark: Option<&str>| range_after(watermark.map(day), today, 30).start;
+        assert_eq!(start(None), day("2025-02-08"));
+        assert_eq!(start(Some("2025-03-06")), day("2025-03-07"));
+        // Never further back than the history window, nor past today
+        assert_eq!(start(Some("2024-01-01")), day("2025-02-08"));
+        assert_eq!(start(Some("2025-03-10")), today);
+    }
+
+    #[test]
+    fn advances_the_watermark_to_the_last_settled_day_before_a_gap() {
+        let today = day("2025-03-10");
+        let range = DateRange {
+            start: day("2025-03-01"),
+            end: today,
+        };
+        let through = |missing: &[&str]| {
+            let missing: Vec<NaiveDate> = missing.iter().map(|d| day(d)).collect();
+            settled_through(range, &missing, today)
+        };
+        assert_eq!(through(&[]), Some(day("2025-03-07")));
+        assert_eq!(
+            through(&["2025-03-09", "2025-03-05"]),
+            Some(day("2025-03-04"))
+        );
+        assert_eq!(through(&["2025-03-01"]), None);
+
+        // Nothing in the range has settled yet
+        let recent = DateRange {
+            start: day("2025-03-08"),
+            end: today,
+        };
+        assert_eq!(settled_through(recent, &[], today), None);
+    }
 }
@@ -0,0 +1,784 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{HourlyUsage, SessionUsage, UsageKind, UsageSnapshot};
+
+/// Rates in USD per 1M tokens, plus a flat per-unit rate for models billed by
+/// something other than tokens.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    pub cache_read: f64,
+    /// Cache writes with the default 5-minute TTL.
+    pub cache_write_5m: f64,
+    /// Cache writes with the extended 1-hour TTL.
+    pub cache_write_1h: f64,
+    /// Fraction taken off every rate for batch API requests (0.5 = half price).
+    pub batch_discount: f64,
+    /// USD per image, character, second of audio or GB-day of storage,
+    /// whichever unit the model's usage is reported in.
+    pub unit_price: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let tokens = usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_read_tokens as f64 * self.cache_read
+            + usage.cache_creation_5m_tokens as f64 * self.cache_write_5m
+            + usage.cache_creation_1h_tokens as f64 * self.cache_write_1h;
+        let full = tokens / 1_000_000.0 + usage.quantity * self.unit_price;
+        let multiplier = if usage.batch {
+            1.0 - self.batch_discount
+        } else {
+            1.0
+        };
+        full * multiplier
+    }
+}
+
+/// Token counts to be costed against a [`ModelPrice`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_read_tokens: i64,
+    pub cache_creation_5m_tokens: i64,
+    pub cache_creation_1h_tokens: i64,
+    /// Non-token usage, costed at `unit_price`.
+    pub quantity: f64,
+    pub batch: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    Builtin,
+    Override,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceEntry {
+    /// Canonical model ID, without a date snapshot suffix.
+    pub model: String,
+    /// Other IDs that bill at the same rates (`-latest` tags, older names).
+    pub aliases: Vec<String>,
+    pub price: ModelPrice,
+    /// First day this price applies; `None` means since the model launched.
+    pub effective_from: Option<NaiveDate>,
+    /// Last day this price applies; `None` means it is still current.
+    pub effective_to: Option<NaiveDate>,
+    pub source: PriceSource,
+}
+
+impl PriceEntry {
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        self.effective_from.is_none_or(|from| from <= date)
+            && self.effective_to.is_none_or(|to| date <= to)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PriceTable {
+    pub entries: Vec<PriceEntry>,
+    /// Override file merged into this table, if one was found.
+    pub override_path: Option<String>,
+    /// Why the override file was ignored, if it could not be applied.
+    pub override_error: Option<String>,
+}
+
+/// Result of costing usage: either a dollar figure or an explicit marker that
+/// the model is missing from the price table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostEstimate {
+    Priced(f64),
+    Unpriced,
+}
+
+impl CostEstimate {
+    /// Dollar amount, counting unpriced usage as zero.
+    pub fn usd(self) -> f64 {
+        match self {
+            CostEstimate::Priced(usd) => usd,
+            CostEstimate::Unpriced => 0.0,
+        }
+    }
+
+    pub fn is_priced(self) -> bool {
+        matches!(self, CostEstimate::Priced(_))
+    }
+}
+
+impl PriceTable {
+    /// Find the price of `model` on `date`. The model is matched by exact ID
+    /// or alias, retrying once with any trailing date snapshot (`-20250514`,
+    /// `-2024-08-06`) removed. When several entries cover the date, overrides
+    /// beat built-ins and the most recently effective entry wins.
+    pub fn lookup(&self, model: &str, date: NaiveDate) -> Option<&PriceEntry> {
+        let canonical = self.canonical_id(model)?;
+        self.entries
+            .iter()
+            .filter(|e| e.model == canonical && e.covers(date))
+            .max_by_key(|e| (e.source == PriceSource::Override, e.effective_from))
+    }
+
+    fn canonical_id(&self, model: &str) -> Option<&str> {
+        self.find_exact(model)
+            .or_else(|| strip_date_suffix(model).and_then(|base| self.find_exact(base)))
+            .map(|e| e.model.as_str())
+    }
+
+    fn find_exact(&self, model: &str) -> Option<&PriceEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.model == model || e.aliases.iter().any(|a| a == model))
+    }
+
+    pub fn estimate(&self, model: &str, date: NaiveDate, usage: &TokenUsage) -> CostEstimate {
+        match self.lookup(model, date) {
+            Some(entry) => CostEstimate::Priced(entry.price.cost(usage)),
+            None => CostEstimate::Unpriced,
+        }
+    }
+}
+
+fn strip_date_suffix(model: &str) -> Option<&str> {
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    // Anthropic style: claude-sonnet-4-20250514
+    if let Some((base, tail)) = model.rsplit_once('-') {
+        if tail.len() == 8 && is_digits(tail) {
+            return Some(base);
+        }
+    }
+    // OpenAI style: gpt-4o-2024-08-06
+    if model.len() > 11 {
+        let (base, tail) = model.split_at(model.len() - 11);
+        let parts: Vec<&str> = tail.split('-').collect();
+        if let ["", y, m, d] = parts.as_slice() {
+            if y.len() == 4
+                && m.len() == 2
+                && d.len() == 2
+                && is_digits(y)
+                && is_digits(m)
+                && is_digits(d)
+            {
+                return Some(base);
+            }
+        }
+    }
+    None
+}
+
+// (model, aliases, input, output, cache_read, cache_write_5m, cache_write_1h)
+type BuiltinRow = (&'static str, &'static [&'static str], f64, f64, f64, f64, f64);
+
+/// Published list prices. Anthropic bills cache reads at 0.1x input and cache
+/// writes at 1.25x (5-minute TTL) or 2x (1-hour TTL). OpenAI has no write
+/// surcharge, so its write rates equal input.
+#[rustfmt::skip]
+const BUILTIN_PRICES: &[BuiltinRow] = &[
+    // Anthropic
+    ("claude-opus-4-5",   &[],                           5.0,  25.0, 0.50,  6.25,  10.0),
+    ("claude-opus-4-1",   &[],                           15.0, 75.0, 1.50,  18.75, 30.0),
+    ("claude-opus-4",     &["claude-opus-4-0"],          15.0, 75.0, 1.50,  18.75, 30.0),
+    ("claude-sonnet-4-5", &[],                           3.0,  15.0, 0.30,  3.75,  6.0),
+    ("claude-sonnet-4",   &["claude-sonnet-4-0"],        3.0,  15.0, 0.30,  3.75,  6.0),
+    ("claude-3-7-sonnet", &["claude-3-7-sonnet-latest"], 3.0,  15.0, 0.30,  3.75,  6.0),
+    ("claude-3-5-sonnet", &["claude-3-5-sonnet-latest"], 3.0,  15.0, 0.30,  3.75,  6.0),
+    ("claude-haiku-4-5",  &[],                           1.0,  5.0,  0.10,  1.25,  2.0),
+    ("claude-3-5-haiku",  &["claude-3-5-haiku-latest"],  0.80, 4.0,  0.08,  1.0,   1.6),
+    ("claude-3-opus",     &["claude-3-opus-latest"],     15.0, 75.0, 1.50,  18.75, 30.0),
+    ("claude-3-haiku",    &[],                           0.25, 1.25, 0.03,  0.30,  0.50),
+    // OpenAI
+    ("gpt-5",             &["gpt-5-chat-latest"],        1.25, 10.0, 0.125, 1.25,  1.25),
+    ("gpt-5-mini",        &[],                           0.25, 2.0,  0.025, 0.25,  0.25),
+    ("gpt-5-nano",        &[],                           0.05, 0.40, 0.005, 0.05,  0.05),
+    ("gpt-4.1",           &[],                           2.0,  8.0,  0.50,  2.0,   2.0),
+    ("gpt-4.1-mini",      &[],                           0.40, 1.60, 0.10,  0.40,  0.40),
+    ("gpt-4.1-nano",      &[],                           0.10, 0.40, 0.025, 0.10,  0.10),
+    ("gpt-4o",            &["chatgpt-4o-latest"],        2.50, 10.0, 1.25,  2.50,  2.50),
+    ("gpt-4o-mini",       &[],                           0.15, 0.60, 0.075, 0.15,  0.15),
+    ("gpt-4-turbo",       &["gpt-4-turbo-preview", "gpt-4-1106-preview", "gpt-4-0125-preview"],
+                                                         10.0, 30.0, 10.0,  10.0,  10.0),
+    ("gpt-4",             &["gpt-4-0613"],               30.0, 60.0, 30.0,  30.0,  30.0),
+    ("gpt-3.5-turbo",     &["gpt-3.5-turbo-0125", "gpt-3.5-turbo-1106"],
+                                                         0.50, 1.50, 0.50,  0.50,  0.50),
+    ("o1",                &["o1-preview"],               15.0, 60.0, 7.50,  15.0,  15.0),
+    ("o1-mini",           &[],                           1.10, 4.40, 0.55,  1.10,  1.10),
+    ("o3",                &[],                           2.0,  8.0,  0.50,  2.0,   2.0),
+    ("o3-mini",           &[],                           1.10, 4.40, 0.55,  1.10,  1.10),
+    ("o4-mini",           &[],                           1.10, 4.40, 0.275, 1.10,  1.10),
+    // OpenAI embeddings and moderation: input tokens only
+    ("text-embedding-3-small", &[],                      0.02, 0.0,  0.02,  0.02,  0.02),
+    ("text-embedding-3-large", &[],                      0.13, 0.0,  0.13,  0.13,  0.13),
+    ("text-embedding-ada-002", &[],                      0.10, 0.0,  0.10,  0.10,  0.10),
+    ("omni-moderation",   &["omni-moderation-latest"],   0.0,  0.0,  0.0,   0.0,   0.0),
+    ("text-moderation",   &["text-moderation-latest", "text-moderation-stable"],
+                                                         0.0,  0.0,  0.0,   0.0,   0.0),
+];
+
+// (model, aliases, USD per unit)
+type BuiltinUnitRow = (&'static str, &'static [&'static str], f64);
+
+/// Models billed per image, character, second or GB-day. Image prices are
+/// for a standard-quality 1024x1024 image, since usage is not broken down by
+/// size; billed amounts from the Costs API correct for the rest.
+#[rustfmt::skip]
+const BUILTIN_UNIT_PRICES: &[BuiltinUnitRow] = &[
+    // per image
+    ("dall-e-3",               &[], 0.040),
+    ("dall-e-2",               &[], 0.020),
+    ("gpt-image-1",            &[], 0.042),
+    // per character of speech
+    ("tts-1",                  &[], 0.000015),
+    ("tts-1-hd",               &[], 0.000030),
+    // per second transcribed
+    ("whisper-1",              &[], 0.0001),
+    ("gpt-4o-transcribe",      &[], 0.0001),
+    ("gpt-4o-mini-transcribe", &[], 0.00005),
+    // per GB-day of vector store storage
+    ("vector-store",           &[], 0.10),
+];
+
+// (model, effective_from, effective_to, input, output, cache_read, cache_write_5m, cache_write_1h)
+type HistoryRow = (
+    &'static str,
+    Option<&'static str>,
+    &'static str,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+);
+
+/// Superseded list prices. The current row for the same model in
+/// `BUILTIN_PRICES` takes effect the day after the latest `effective_to`.
+#[rustfmt::skip]
+const BUILTIN_HISTORY: &[HistoryRow] = &[
+    ("o1-mini", None, "2025-01-30", 3.0, 12.0, 1.50, 3.0, 3.0),
+];
+
+fn builtin_date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").expect("valid date in built-in price table")
+}
+
+impl PriceTable {
+    pub fn builtin() -> Self {
+        let price = |input, output, cache_read, cache_write_5m, cache_write_1h| ModelPrice {
+            input,
+            output,
+            cache_read,
+            cache_write_5m,
+            cache_write_1h,
+            batch_discount: 0.5,
+            unit_price: 0.0,
+        };
+
+        let mut entries: Vec<PriceEntry> = BUILTIN_HISTORY
+            .iter()
+            .map(|&(model, from, to, input, output, read, write_5m, write_1h)| PriceEntry {
+                model: model.to_string(),
+                aliases: Vec::new(),
+                price: price(input, output, read, write_5m, write_1h),
+                effective_from: from.map(builtin_date),
+                effective_to: Some(builtin_date(to)),
+                source: PriceSource::Builtin,
+            })
+            .collect();
+
+        for &(model, aliases, input, output, read, write_5m, write_1h) in BUILTIN_PRICES {
+            let effective_from = entries
+                .iter()
+                .filter(|e| e.model == model)
+                .filter_map(|e| e.effective_to)
+                .max()
+                .and_then(|last| last.succ_opt());
+            entries.push(PriceEntry {
+                model: model.to_string(),
+                aliases: aliases.iter().map(|a| a.to_string()).collect(),
+                price: price(input, output, read, write_5m, write_1h),
+                effective_from,
+                effective_to: None,
+                source: PriceSource::Builtin,
+            });
+        }
+
+        for &(model, aliases, unit_price) in BUILTIN_UNIT_PRICES {
+            entries.push(PriceEntry {
+                model: model.to_string(),
+                aliases: aliases.iter().map(|a| a.to_string()).collect(),
+                price: ModelPrice {
+                    unit_price,
+                    ..price(0.0, 0.0, 0.0, 0.0, 0.0)
+                },
+                effective_from: None,
+                effective_to: None,
+                source: PriceSource::Builtin,
+            });
+        }
+
+        Self {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    /// Add user overrides to this table. Each override becomes its own entry
+    /// that wins over built-ins on the days it covers. Fields left out keep
+    /// the built-in value in effect on each day, so an override spanning a
+    /// built-in price change is split at that change; models not in the
+    /// table need at least `input` and `output`, or `unit_price`.
+    fn apply_overrides(&mut self, file: OverrideFile) -> Result<(), String> {
+        for (model, overrides) in file.models {
+            for o in overrides.into_vec() {
+                let canonical = self.canonical_id(&model).map(str::to_string);
+                let bases: Vec<(Option<NaiveDate>, Option<NaiveDate>, ModelPrice)> = self
+                    .entries
+                    .iter()
+                    .filter(|e| Some(&e.model) == canonical.as_ref())
+                    .filter(|e| e.source == PriceSource::Builtin)
+                    .filter_map(|e| {
+                        let from = e.effective_from.max(o.effective_from);
+                        let to = match (e.effective_to, o.effective_to) {
+                            (Some(a), Some(b)) => Some(a.min(b)),
+                            (a, b) => a.or(b),
+                        };
+                        match (from, to) {
+                            (Some(from), Some(to)) if from > to => None,
+                            _ => Some((from, to, e.price)),
+                        }
+                    })
+                    .collect();
+
+                let prices = if bases.is_empty() {
+                    let (input, output) = match (o.input, o.output, o.unit_price) {
+                        (Some(input), Some(output), _) => (input, output),
+                        (None, None, Some(_)) => (0.0, 0.0),
+                        _ => {
+                            return Err(format!(
+                                "`{model}` is not a built-in model, so `input` and `output` (or `unit_price`) are required"
+                            ))
+                        }
+                    };
+                    let price = ModelPrice {
+                        input,
+                        output,
+                        cache_read: o.cache_read.unwrap_or(input),
+                        cache_write_5m: o.cache_write_5m.unwrap_or(input),
+                        cache_write_1h: o.cache_write_1h.unwrap_or(input),
+                        batch_discount: o.batch_discount.unwrap_or(0.0),
+                        unit_price: o.unit_price.unwrap_or(0.0),
+                    };
+                    vec![(o.effective_from, o.effective_to, price)]
+                } else {
+                    bases
+                        .into_iter()
+                        .map(|(from, to, p)| {
+                            let price = ModelPrice {
+                                input: o.input.unwrap_or(p.input),
+                                output: o.output.unwrap_or(p.output),
+                                cache_read: o.cache_read.unwrap_or(p.cache_read),
+                                cache_write_5m: o.cache_write_5m.unwrap_or(p.cache_write_5m),
+                                cache_write_1h: o.cache_write_1h.unwrap_or(p.cache_write_1h),
+                                batch_discount: o.batch_discount.unwrap_or(p.batch_discount),
+                                unit_price: o.unit_price.unwrap_or(p.unit_price),
+                            };
+                            (from, to, price)
+                        })
+                        .collect()
+                };
+
+                for (effective_from, effective_to, price) in prices {
+                    self.entries.push(PriceEntry {
+                        model: canonical.clone().unwrap_or_else(|| model.clone()),
+                        aliases: o.aliases.clone(),
+                        price,
+                        effective_from,
+                        effective_to,
+                        source: PriceSource::Override,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ─── User overrides ──────────────────────────────────────────────────────────
+
+/// Override files looked up in the app's local data dir, in priority order.
+pub const OVERRIDE_FILES: &[&str] = &["pricing.toml", "pricing.json"];
+
+/// Shape of `pricing.toml` / `pricing.json`. A model takes either a single
+/// override or a list of effective-dated ones:
+///
+/// ```toml
+/// [models."claude-sonnet-4-5"]
+/// input = 2.70
+/// output = 13.50
+///
+/// [[models."gpt-4o"]]
+/// effective_to = "2025-03-31"
+/// input = 2.25
+///
+/// [[models."gpt-4o"]]
+/// effective_from = "2025-04-01"
+/// input = 2.00
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct OverrideFile {
+    #[serde(default)]
+    models: BTreeMap<String, OneOrMany>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(PriceOverride),
+    Many(Vec<PriceOverride>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<PriceOverride> {
+        match self {
+            OneOrMany::One(o) => vec![o],
+            OneOrMany::Many(list) => list,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceOverride {
+    input: Option<f64>,
+    output: Option<f64>,
+    cache_read: Option<f64>,
+    #[serde(alias = "cache_write")]
+    cache_write_5m: Option<f64>,
+    cache_write_1h: Option<f64>,
+    batch_discount: Option<f64>,
+    unit_price: Option<f64>,
+    effective_from: Option<NaiveDate>,
+    effective_to: Option<NaiveDate>,
+    #[serde(default)]
+    aliases: Vec<String>,
+}
+
+fn find_override_file(data_dir: &Path) -> Option<PathBuf> {
+    OVERRIDE_FILES
+        .iter()
+        .map(|name| data_dir.join(name))
+        .find(|p| p.is_file())
+}
+
+fn parse_override_file(path: &Path, contents: &str) -> Result<OverrideFile, String> {
+    if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Build the effective table: built-in prices with the override file from
+/// `data_dir` merged on top. A broken override file is reported on the table
+/// and otherwise ignored, so a typo never zeroes out every cost.
+pub async fn load_table(data_dir: &Path) -> PriceTable {
+    let mut table = PriceTable::builtin();
+    let Some(path) = find_override_file(data_dir) else {
+        return table;
+    };
+    table.override_path = Some(path.display().to_string());
+
+    let merged = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => parse_override_file(&path, &contents).and_then(|file| {
+            let mut merged = PriceTable::builtin();
+            merged.apply_overrides(file)?;
+            Ok(merged.entries)
+        }),
+        Err(e) => Err(e.to_string()),
+    };
+    match merged {
+        Ok(entries) => table.entries = entries,
+        Err(e) => table.override_error = Some(e),
+    }
+    table
+}
+
+/// Reload the shared table whenever the override file in `data_dir` appears,
+/// changes or is removed, calling `on_change` after each reload. Runs
+/// forever; spawn it once at startup.
+pub async fn watch_overrides(data_dir: PathBuf, on_change: impl Fn()) {
+    let mut last_seen: Option<(PathBuf, SystemTime, u64)> = None;
+    loop {
+        let current = find_override_file(&data_dir).and_then(|path| {
+            let meta = std::fs::metadata(&path).ok()?;
+            Some((path, meta.modified().ok()?, meta.len()))
+        });
+        if current != last_seen {
+            set_table(load_table(&data_dir).await);
+            last_seen = current;
+            on_change();
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+// ─── Shared table ────────────────────────────────────────────────────────────
+
+fn shared() -> &'static RwLock<Arc<PriceTable>> {
+    static TABLE: OnceLock<RwLock<Arc<PriceTable>>> = OnceLock::new();
+    TABLE.get_or_init(|| RwLock::new(Arc::new(PriceTable::builtin())))
+}
+
+/// The price table every fetcher costs usage against.
+pub fn table() -> Arc<PriceTable> {
+    shared()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+pub fn set_table(table: PriceTable) {
+    *shared().write().unwrap_or_else(|e| e.into_inner()) = Arc::new(table);
+}
+
+/// Cost `usage` of `model` on `date` against the shared price table.
+pub fn estimate_cost(model: &str, date: NaiveDate, usage: &TokenUsage) -> CostEstimate {
+    table().estimate(model, date, usage)
+}
+
+/// Recompute `estimated_cost_usd` and `unpriced` from the snapshot's own token
+/// counts and quantity, at the prices in effect on its `date`. `cost_usd`
+/// follows the billed amount when there is one and the estimate otherwise.
+pub fn price_snapshot(snapshot: &mut UsageSnapshot) {
+    let date = day_of(&snapshot.date);
+    let usage = TokenUsage {
+        input_tokens: snapshot.input_tokens,
+        output_tokens: snapshot.output_tokens,
+        cache_read_tokens: snapshot.cache_tokens,
+        cache_creation_5m_tokens: snapshot.cache_creation_5m_tokens,
+        cache_creation_1h_tokens: snapshot.cache_creation_1h_tokens,
+        quantity: snapshot.quantity,
+        batch: snapshot.usage_kind == UsageKind::Batch,
+    };
+    let estimate = estimate_cost(&snapshot.model, date, &usage);
+    snapshot.estimated_cost_usd = estimate.usd();
+    snapshot.cost_usd = snapshot.billed_cost_usd.unwrap_or(snapshot.estimated_cost_usd);
+    // A billed amount is authoritative even when we have no price for the model
+    snapshot.unpriced = !estimate.is_priced() && snapshot.billed_cost_usd.is_none();
+}
+
+/// Recompute an hourly row's cost at the prices in effect on its day. Hours
+/// never carry a billed amount.
+pub fn price_hourly(row: &mut HourlyUsage) {
+    let usage = TokenUsage {
+        input_tokens: row.input_tokens,
+        output_tokens: row.output_tokens,
+        cache_read_tokens: row.cache_tokens,
+        cache_creation_5m_tokens: row.cache_creation_5m_tokens,
+        cache_creation_1h_tokens: row.cache_creation_1h_tokens,
+        quantity: row.quantity,
+        batch: row.usage_kind == UsageKind::Batch,
+    };
+    let estimate = estimate_cost(&row.model, day_of(&row.hour), &usage);
+    row.cost_usd = estimate.usd();
+    row.unpriced = !estimate.is_priced();
+}
+
+/// Recompute a Claude Code session row's cost at the prices in effect on its
+/// day.
+pub fn price_session(row: &mut SessionUsage) {
+    let usage = TokenUsage {
+        input_tokens: row.input_tokens,
+        output_tokens: row.output_tokens,
+        cache_read_tokens: row.cache_tokens,
+        cache_creation_5m_tokens: row.cache_creation_5m_tokens,
+        cache_creation_1h_tokens: row.cache_creation_1h_tokens,
+        quantity: 0.0,
+        // Claude Code talks to the Messages API directly, never in batches
+        batch: false,
+    };
+    let estimate = estimate_cost(&row.model, day_of(&row.date), &usage);
+    row.cost_usd = estimate.usd();
+    row.unpriced = !estimate.is_priced();
+}
+
+/// The day a `YYYY-MM-DD` date or `YYYY-MM-DDTHH:...` hour falls on.
+fn day_of(stamp: &str) -> NaiveDate {
+    stamp
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .unwrap_or_else(|| Utc::now().date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Unit, UsageKind};
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn strips_date_suffixes() {
+        assert_eq!(
+            strip_date_suffix("claude-sonnet-4-20250514"),
+            Some("claude-sonnet-4")
+        );
+        assert_eq!(strip_date_suffix("gpt-4o-2024-08-06"), Some("gpt-4o"));
+        assert_eq!(strip_date_suffix("gpt-4o-mini"), None);
+        assert_eq!(strip_date_suffix("gpt-4-0613"), None);
+        assert_eq!(strip_date_suffix("claude-3-5-sonnet-latest"), None);
+        assert_eq!(strip_date_suffix("2024-08-06"), None);
+    }
+
+    #[test]
+    fn looks_up_models_by_id_alias_and_snapshot() {
+        let table = PriceTable::builtin();
+        let on = day("2025-06-01");
+        let model = |id: &str| table.lookup(id, on).map(|e| e.model.as_str());
+
+        assert_eq!(model("gpt-4o"), Some("gpt-4o"));
+        assert_eq!(model("chatgpt-4o-latest"), Some("gpt-4o"));
+        assert_eq!(model("gpt-4o-2024-08-06"), Some("gpt-4o"));
+        assert_eq!(model("gpt-4o-mini-2024-07-18"), Some("gpt-4o-mini"));
+        assert_eq!(model("claude-sonnet-4-20250514"), Some("claude-sonnet-4"));
+        assert_eq!(model("claude-sonnet-4-0"), Some("claude-sonnet-4"));
+        assert_eq!(model("<synthetic>"), None);
+        assert_eq!(
+            table.estimate("<synthetic>", on, &TokenUsage::default()),
+            CostEstimate::Unpriced
+        );
+    }
+
+    #[test]
+    fn batch_usage_gets_the_discount() {
+        let table = PriceTable::builtin();
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            ..Default::default()
+        };
+        let on = day("2025-06-01");
+        assert_eq!(table.estimate("gpt-4o", on, &usage).usd(), 12.5);
+        let batch = TokenUsage {
+            batch: true,
+            ..usage
+        };
+        assert_eq!(table.estimate("gpt-4o", on, &batch).usd(), 6.25);
+    }
+
+    #[test]
+    fn prices_batch_snapshots_at_the_discount() {
+        let mut snapshot = UsageSnapshot {
+            id: None,
+            provider: "anthropic".to_string(),
+            model: "claude-sonnet-4-20250514".to_string(),
+            date: "2025-06-01".to_string(),
+            input_tokens: 1_000_000,
+            output_tokens: 0,
+            cache_tokens: 0,
+            cache_creation_5m_tokens: 0,
+            cache_creation_1h_tokens: 0,
+            cost_usd: 0.0,
+            estimated_cost_usd: 0.0,
+            billed_cost_usd: None,
+            usage_kind: UsageKind::Text,
+            unit: Unit::Tokens,
+            quantity: 0.0,
+            fetched_at: String::new(),
+            unpriced: false,
+        };
+        price_snapshot(&mut snapshot);
+        assert_eq!(snapshot.cost_usd, 3.0);
+
+        snapshot.usage_kind = UsageKind::Batch;
+        price_snapshot(&mut snapshot);
+        assert_eq!(snapshot.cost_usd, 1.5);
+        assert!(!snapshot.unpriced);
+    }
+
+    #[test]
+    fn uses_the_price_in_effect_on_each_day() {
+        let table = PriceTable::builtin();
+        let input = |on: &str| table.lookup("o1-mini", day(on)).unwrap().price.input;
+        assert_eq!(input("2024-12-01"), 3.0);
+        assert_eq!(input("2025-01-30"), 3.0);
+        assert_eq!(input("2025-01-31"), 1.10);
+    }
+
+    #[test]
+    fn dated_overrides_win_on_the_days_they_cover() {
+        let file = parse_override_file(
+            Path::new("pricing.toml"),
+            r#"
+                [models."claude-sonnet-4-0"]
+                input = 2.70
+
+                [[models."gpt-4o"]]
+                effective_to = "2025-03-31"
+                input = 2.25
+
+                [[models."gpt-4o"]]
+                effective_from = "2025-04-01"
+                input = 2.00
+
+                [models."my-model"]
+                input = 1.0
+                output = 2.0
+            "#,
+        )
+        .unwrap();
+        let mut table = PriceTable::builtin();
+        table.apply_overrides(file).unwrap();
+        let price = |model: &str, on: &str| table.lookup(model, day(on)).unwrap().price;
+
+        // Fields left out keep the built-in rate
+        let sonnet = price("claude-sonnet-4-20250514", "2025-06-01");
+        assert_eq!((sonnet.input, sonnet.output), (2.70, 15.0));
+        assert_eq!(price("gpt-4o", "2025-03-31").input, 2.25);
+        assert_eq!(price("gpt-4o", "2025-04-01").input, 2.00);
+        assert_eq!(price("gpt-4o", "2025-04-01").output, 10.0);
+        assert_eq!(price("my-model", "2025-06-01").output, 2.0);
+    }
+
+    #[test]
+    fn undated_overrides_keep_the_built_in_rate_of_each_day() {
+        let file = parse_override_file(
+            Path::new("pricing.toml"),
+            r#"
+                [models."o1-mini"]
+                output = 4.0
+            "#,
+        )
+        .unwrap();
+        let mut table = PriceTable::builtin();
+        table.apply_overrides(file).unwrap();
+        let rates = |on: &str| {
+            let price = table.lookup("o1-mini", day(on)).unwrap().price;
+            (price.input, price.output)
+        };
+
+        assert_eq!(rates("2024-12-01"), (3.0, 4.0));
+        assert_eq!(rates("2025-01-31"), (1.10, 4.0));
+    }
+
+    #[test]
+    fn rejects_unknown_models_without_rates() {
+        let file = parse_override_file(
+            Path::new("pricing.json"),
+            r#"{"models": {"x": {"input": 1.0}}}"#,
+        )
+        .unwrap();
+        assert!(PriceTable::builtin().apply_overrides(file).is_err());
+    }
+}
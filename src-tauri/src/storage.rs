@@ -1,5 +1,103 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Datelike, Duration, DurationRound, NaiveDate, SecondsFormat};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
 use serde::{Deserialize, Serialize};
 
+use crate::api::DateRange;
+use crate::pricing;
+use crate::timezone;
+
+/// What a snapshot measures. Chat/messages usage is `Text`, or `Batch` when
+/// it went through a batch API at the discounted rate; the rest are OpenAI's
+/// other billable APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageKind {
+    #[default]
+    Text,
+    Batch,
+    Embeddings,
+    Moderation,
+    Images,
+    AudioSpeech,
+    AudioTranscription,
+    VectorStore,
+}
+
+/// What `UsageSnapshot::quantity` counts for non-token usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    #[default]
+    Tokens,
+    Images,
+    Characters,
+    Seconds,
+    GbDays,
+}
+
+impl UsageKind {
+    pub const ALL: &'static [UsageKind] = &[
+        UsageKind::Text,
+        UsageKind::Batch,
+        UsageKind::Embeddings,
+        UsageKind::Moderation,
+        UsageKind::Images,
+        UsageKind::AudioSpeech,
+        UsageKind::AudioTranscription,
+        UsageKind::VectorStore,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UsageKind::Text => "text",
+            UsageKind::Batch => "batch",
+            UsageKind::Embeddings => "embeddings",
+            UsageKind::Moderation => "moderation",
+            UsageKind::Images => "images",
+            UsageKind::AudioSpeech => "audio_speech",
+            UsageKind::AudioTranscription => "audio_transcription",
+            UsageKind::VectorStore => "vector_store",
+        }
+    }
+
+    pub fn unit(self) -> Unit {
+        match self {
+            UsageKind::Text | UsageKind::Batch | UsageKind::Embeddings | UsageKind::Moderation => {
+                Unit::Tokens
+            }
+            UsageKind::Images => Unit::Images,
+            UsageKind::AudioSpeech => Unit::Characters,
+            UsageKind::AudioTranscription => Unit::Seconds,
+            UsageKind::VectorStore => Unit::GbDays,
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|k| k.as_str() == s)
+            .unwrap_or_default()
+    }
+}
+
+impl Unit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Tokens => "tokens",
+            Unit::Images => "images",
+            Unit::Characters => "characters",
+            Unit::Seconds => "seconds",
+            Unit::GbDays => "gb_days",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageSnapshot {
     pub id: Option<i64>,
@@ -8,15 +106,270 @@ pub struct UsageSnapshot {
     pub date: String,
     pub input_tokens: i64,
     pub output_tokens: i64,
+    /// Prompt-cache reads.
     pub cache_tokens: i64,
+    /// Prompt-cache writes with the default 5-minute TTL.
+    #[serde(default)]
+    pub cache_creation_5m_tokens: i64,
+    /// Prompt-cache writes with the extended 1-hour TTL.
+    #[serde(default)]
+    pub cache_creation_1h_tokens: i64,
+    /// What the row costs: the billed amount when the provider reports one,
+    /// otherwise the token-based estimate.
     pub cost_usd: f64,
+    /// Estimate from the price table, kept even when billed.
+    #[serde(default)]
+    pub estimated_cost_usd: f64,
+    /// Amount the provider actually billed, where it exposes one.
+    #[serde(default)]
+    pub billed_cost_usd: Option<f64>,
     pub fetched_at: String,
+    #[serde(default)]
+    pub usage_kind: UsageKind,
+    #[serde(default)]
+    pub unit: Unit,
+    /// Amount used in `unit`, for usage not measured in tokens (images
+    /// generated, seconds transcribed, ...). Zero for token usage.
+    #[serde(default)]
+    pub quantity: f64,
+    /// Set when the model has no entry in the price table and `cost_usd`
+    /// therefore does not include it.
+    #[serde(default)]
+    pub unpriced: bool,
+}
+
+impl UsageSnapshot {
+    /// Add `other`'s token counts and quantity to this row. Costs are left
+    /// alone; re-price afterwards.
+    pub fn add_usage(&mut self, other: &UsageSnapshot) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_tokens += other.cache_tokens;
+        self.cache_creation_5m_tokens += other.cache_creation_5m_tokens;
+        self.cache_creation_1h_tokens += other.cache_creation_1h_tokens;
+        self.quantity += other.quantity;
+    }
 }
 
-/// SQL to initialise the database — called once at startup via the frontend
-/// using tauri-plugin-sql's `execute` from JavaScript.
-/// We expose these as constants so the frontend can run them.
-pub const INIT_SQL: &str = r#"
+/// Usage within one UTC hour. Daily snapshots are the sum of these; providers
+/// only bill per day, so `cost_usd` here is always the estimate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyUsage {
+    pub provider: String,
+    pub model: String,
+    /// Start of the hour, `YYYY-MM-DDTHH:00:00Z`.
+    pub hour: String,
+    pub usage_kind: UsageKind,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_tokens: i64,
+    pub cache_creation_5m_tokens: i64,
+    pub cache_creation_1h_tokens: i64,
+    pub quantity: f64,
+    pub cost_usd: f64,
+    pub unpriced: bool,
+    pub fetched_at: String,
+}
+
+impl HourlyUsage {
+    /// The hour-`hour` row for a snapshot that holds just that hour's usage.
+    pub fn new(hour: String, snapshot: &UsageSnapshot) -> Self {
+        Self {
+            provider: snapshot.provider.clone(),
+            model: snapshot.model.clone(),
+            hour,
+            usage_kind: snapshot.usage_kind,
+            input_tokens: snapshot.input_tokens,
+            output_tokens: snapshot.output_tokens,
+            cache_tokens: snapshot.cache_tokens,
+            cache_creation_5m_tokens: snapshot.cache_creation_5m_tokens,
+            cache_creation_1h_tokens: snapshot.cache_creation_1h_tokens,
+            quantity: snapshot.quantity,
+            cost_usd: 0.0,
+            unpriced: false,
+            fetched_at: snapshot.fetched_at.clone(),
+        }
+    }
+}
+
+/// Claude Code usage of one session on one day, per model. Subagent runs
+/// count towards the session that started them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub session_id: String,
+    /// Directory the session was started in.
+    pub project: String,
+    pub model: String,
+    pub date: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_tokens: i64,
+    pub cache_creation_5m_tokens: i64,
+    pub cache_creation_1h_tokens: i64,
+    pub cost_usd: f64,
+    pub unpriced: bool,
+    pub fetched_at: String,
+}
+
+/// One assistant response read from a Claude Code log. `(message_id,
+/// request_id)` identifies it across every log it was copied into.
+#[derive(Debug, Clone)]
+pub struct ClaudeCodeEvent {
+    pub message_id: String,
+    pub request_id: String,
+    pub session_id: String,
+    pub subagent: bool,
+    pub model: String,
+    /// Start of the UTC hour it was logged in, `YYYY-MM-DDTHH:00:00Z`.
+    pub hour: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_tokens: i64,
+    pub cache_creation_5m_tokens: i64,
+    pub cache_creation_1h_tokens: i64,
+}
+
+/// How far a Claude Code log has been read.
+#[derive(Debug, Clone)]
+pub struct LogCursor {
+    pub path: String,
+    pub inode: u64,
+    /// File size when the cursor was saved.
+    pub size: u64,
+    /// Byte offset just past the last complete line read.
+    pub offset: u64,
+    /// Length and hash of the line ending at `offset`, to notice a file that
+    /// was rewritten in place.
+    pub last_line_len: u64,
+    pub last_line_hash: String,
+}
+
+/// New lines read from one Claude Code log, stored together with the cursor
+/// that marks them as read.
+#[derive(Debug, Clone)]
+pub struct LogBatch {
+    pub cursor: LogCursor,
+    pub events: Vec<ClaudeCodeEvent>,
+    pub session_id: String,
+    pub project: String,
+    /// Label of the Claude Code config directory the log is in.
+    pub profile: String,
+    /// `project` was guessed from the directory name, so an already stored
+    /// project is kept.
+    pub project_guessed: bool,
+}
+
+/// Claude Code tokens summed per hour, model, session and subagent flag.
+#[derive(Debug, Clone)]
+pub struct ClaudeCodeUsage {
+    pub hour: String,
+    pub model: String,
+    pub session_id: String,
+    pub project: String,
+    pub subagent: bool,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_tokens: i64,
+    pub cache_creation_5m_tokens: i64,
+    pub cache_creation_1h_tokens: i64,
+}
+
+/// File name of the usage database inside the app config dir. This is where
+/// tauri-plugin-sql kept it when the frontend owned the database, so history
+/// from those versions is picked up as-is.
+pub const DB_FILE: &str = "token_tracker.db";
+
+// ─── Schema migrations ───────────────────────────────────────────────────────
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Every schema change, in order. Append new migrations with the next version
+/// number; never edit one that has shipped.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        apply: |tx| tx.execute_batch(V1_SQL),
+    },
+    Migration {
+        version: 2,
+        description: "prompt-cache write tokens and unpriced flag",
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE usage_snapshots ADD COLUMN cache_creation_5m_tokens INTEGER DEFAULT 0;
+                 ALTER TABLE usage_snapshots ADD COLUMN cache_creation_1h_tokens INTEGER DEFAULT 0;
+                 ALTER TABLE usage_snapshots ADD COLUMN unpriced INTEGER DEFAULT 0;",
+            )
+        },
+    },
+    Migration {
+        version: 3,
+        description: "billed cost alongside the estimate",
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE usage_snapshots ADD COLUMN estimated_cost_usd REAL DEFAULT 0;
+                 ALTER TABLE usage_snapshots ADD COLUMN billed_cost_usd REAL;
+                 UPDATE usage_snapshots SET estimated_cost_usd = cost_usd;",
+            )
+        },
+    },
+    Migration {
+        version: 4,
+        description: "usage kind and unit, part of the snapshot key",
+        apply: |tx| tx.execute_batch(V4_SQL),
+    },
+    Migration {
+        version: 5,
+        description: "per-provider fetch watermarks",
+        apply: |tx| {
+            tx.execute_batch(
+                "CREATE TABLE fetch_watermarks (
+                   provider TEXT PRIMARY KEY,
+                   complete_through TEXT NOT NULL,
+                   updated_at TEXT NOT NULL
+                 );",
+            )
+        },
+    },
+    Migration {
+        version: 6,
+        description: "resumable backfill jobs",
+        apply: |tx| {
+            tx.execute_batch(
+                "CREATE TABLE backfill_jobs (
+                   provider TEXT PRIMARY KEY,
+                   start_date TEXT NOT NULL,
+                   end_date TEXT NOT NULL,
+                   next_date TEXT NOT NULL,
+                   updated_at TEXT NOT NULL
+                 );",
+            )
+        },
+    },
+    Migration {
+        version: 7,
+        description: "hourly usage",
+        apply: |tx| tx.execute_batch(V7_SQL),
+    },
+    Migration {
+        version: 8,
+        description: "Claude Code sessions and projects",
+        apply: |tx| tx.execute_batch(V8_SQL),
+    },
+    Migration {
+        version: 9,
+        description: "Claude Code log cursors and events",
+        apply: |tx| tx.execute_batch(V9_SQL),
+    },
+];
+
+/// The schema as first shipped, when the frontend created it through
+/// tauri-plugin-sql. `IF NOT EXISTS` lets it adopt those databases.
+const V1_SQL: &str = r#"
 CREATE TABLE IF NOT EXISTS usage_snapshots (
   id INTEGER PRIMARY KEY AUTOINCREMENT,
   provider TEXT NOT NULL,
@@ -35,3 +388,1507 @@ CREATE TABLE IF NOT EXISTS settings (
   value TEXT NOT NULL
 );
 "#;
+
+/// Sessions hold the project and the Claude Code profile they were read
+/// from, so usage rows need not repeat them.
+const V8_SQL: &str = r#"
+CREATE TABLE claude_code_sessions (
+  session_id TEXT PRIMARY KEY,
+  project TEXT NOT NULL,
+  profile TEXT NOT NULL DEFAULT '',
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE claude_code_session_usage (
+  session_id TEXT NOT NULL,
+  model TEXT NOT NULL,
+  date TEXT NOT NULL,
+  input_tokens INTEGER DEFAULT 0,
+  output_tokens INTEGER DEFAULT 0,
+  cache_tokens INTEGER DEFAULT 0,
+  cache_creation_5m_tokens INTEGER DEFAULT 0,
+  cache_creation_1h_tokens INTEGER DEFAULT 0,
+  cost_usd REAL DEFAULT 0,
+  unpriced INTEGER DEFAULT 0,
+  fetched_at TEXT NOT NULL,
+  PRIMARY KEY (session_id, model, date)
+);
+
+CREATE INDEX claude_code_session_usage_date ON claude_code_session_usage (date);
+"#;
+
+/// Claude Code logs are read incrementally into `claude_code_events`, which
+/// usage is then summed from.
+const V9_SQL: &str = r#"
+CREATE TABLE claude_code_log_cursors (
+  path TEXT PRIMARY KEY,
+  inode INTEGER NOT NULL,
+  size INTEGER NOT NULL,
+  byte_offset INTEGER NOT NULL,
+  last_line_len INTEGER NOT NULL,
+  last_line_hash TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE claude_code_events (
+  message_id TEXT NOT NULL,
+  request_id TEXT NOT NULL,
+  session_id TEXT NOT NULL,
+  subagent INTEGER NOT NULL DEFAULT 0,
+  model TEXT NOT NULL,
+  hour TEXT NOT NULL,
+  input_tokens INTEGER DEFAULT 0,
+  output_tokens INTEGER DEFAULT 0,
+  cache_tokens INTEGER DEFAULT 0,
+  cache_creation_5m_tokens INTEGER DEFAULT 0,
+  cache_creation_1h_tokens INTEGER DEFAULT 0,
+  PRIMARY KEY (message_id, request_id)
+);
+
+CREATE INDEX claude_code_events_hour ON claude_code_events (hour);
+"#;
+
+/// SQLite cannot change a UNIQUE constraint in place, so the table is rebuilt
+/// with `usage_kind` added to the key.
+const V4_SQL: &str = r#"
+CREATE TABLE usage_snapshots_v4 (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  provider TEXT NOT NULL,
+  model TEXT NOT NULL,
+  date TEXT NOT NULL,
+  input_tokens INTEGER DEFAULT 0,
+  output_tokens INTEGER DEFAULT 0,
+  cache_tokens INTEGER DEFAULT 0,
+  cost_usd REAL DEFAULT 0,
+  fetched_at TEXT NOT NULL,
+  cache_creation_5m_tokens INTEGER DEFAULT 0,
+  cache_creation_1h_tokens INTEGER DEFAULT 0,
+  unpriced INTEGER DEFAULT 0,
+  estimated_cost_usd REAL DEFAULT 0,
+  billed_cost_usd REAL,
+  usage_kind TEXT NOT NULL DEFAULT 'text',
+  unit TEXT NOT NULL DEFAULT 'tokens',
+  quantity REAL NOT NULL DEFAULT 0,
+  UNIQUE(provider, model, date, usage_kind)
+);
+
+INSERT INTO usage_snapshots_v4
+  (id, provider, model, date, input_tokens, output_tokens, cache_tokens, cost_usd, fetched_at,
+   cache_creation_5m_tokens, cache_creation_1h_tokens, unpriced, estimated_cost_usd,
+   billed_cost_usd)
+SELECT
+  id, provider, model, date, input_tokens, output_tokens, cache_tokens, cost_usd, fetched_at,
+  cache_creation_5m_tokens, cache_creation_1h_tokens, unpriced, estimated_cost_usd,
+  billed_cost_usd
+FROM usage_snapshots;
+
+DROP TABLE usage_snapshots;
+ALTER TABLE usage_snapshots_v4 RENAME TO usage_snapshots;
+"#;
+
+const V7_SQL: &str = r#"
+CREATE TABLE usage_hourly (
+  provider TEXT NOT NULL,
+  model TEXT NOT NULL,
+  hour TEXT NOT NULL,
+  usage_kind TEXT NOT NULL DEFAULT 'text',
+  input_tokens INTEGER DEFAULT 0,
+  output_tokens INTEGER DEFAULT 0,
+  cache_tokens INTEGER DEFAULT 0,
+  cache_creation_5m_tokens INTEGER DEFAULT 0,
+  cache_creation_1h_tokens INTEGER DEFAULT 0,
+  quantity REAL NOT NULL DEFAULT 0,
+  cost_usd REAL DEFAULT 0,
+  unpriced INTEGER DEFAULT 0,
+  fetched_at TEXT NOT NULL,
+  PRIMARY KEY (provider, model, hour, usage_kind)
+);
+
+CREATE INDEX usage_hourly_hour ON usage_hourly (hour);
+"#;
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+           version INTEGER PRIMARY KEY,
+           description TEXT NOT NULL,
+           applied_at TEXT NOT NULL
+         )",
+    )?;
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Bring the database at `path` up to the latest schema. Before touching a
+/// database that already holds data, a copy is written next to it as
+/// `<file>.pre-v<N>.bak`; all pending migrations then run in one transaction,
+/// so a failure leaves the database exactly as it was.
+fn migrate(conn: &mut Connection, path: &Path) -> Result<(), String> {
+    let current = schema_version(conn).map_err(|e| e.to_string())?;
+    let latest = MIGRATIONS.last().map_or(0, |m| m.version);
+    if current > latest {
+        return Err(format!(
+            "Database schema v{current} is newer than this version of Token Tracker supports (v{latest})"
+        ));
+    }
+    if current == latest {
+        return Ok(());
+    }
+
+    let has_data: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'usage_snapshots')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if has_data {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".pre-v{latest}.bak"));
+        let backup = std::path::PathBuf::from(backup);
+        let _ = std::fs::remove_file(&backup);
+        conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])
+            .map_err(|e| format!("Cannot back up database before migrating: {e}"))?;
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for m in MIGRATIONS.iter().filter(|m| m.version > current) {
+        (m.apply)(&tx)
+            .map_err(|e| format!("Migration v{} ({}) failed: {e}", m.version, m.description))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![m.version, m.description, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// ─── Aggregates ──────────────────────────────────────────────────────────────
+
+/// Which column daily totals are broken down by.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Provider,
+    Model,
+}
+
+impl GroupBy {
+    fn column(self) -> &'static str {
+        match self {
+            GroupBy::Provider => "provider",
+            GroupBy::Model => "model",
+        }
+    }
+}
+
+/// Cost for one day, split by the requested group. Group keys are flattened
+/// next to `date` and `total` so the chart can use them directly as series.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyTotal {
+    pub date: String,
+    pub total: f64,
+    pub tokens: i64,
+    #[serde(flatten)]
+    pub groups: BTreeMap<String, f64>,
+}
+
+/// Cost for one hour of a day, split like [`DailyTotal`].
+#[derive(Debug, Clone, Serialize)]
+pub struct HourlyTotal {
+    /// Start of the hour in UTC, `YYYY-MM-DDTHH:00:00Z`.
+    pub hour: String,
+    pub total: f64,
+    pub tokens: i64,
+    #[serde(flatten)]
+    pub groups: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelTotal {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub total_tokens: i64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderTotal {
+    pub provider: String,
+    pub cost_usd: f64,
+    pub tokens: i64,
+}
+
+/// Estimated vs. billed cost for one provider on one day. Providers bill by
+/// UTC day, so the estimate is summed over the same UTC day from the hourly
+/// rows; `date` is the local day the bill was assigned to. Only models that
+/// have a billed amount are counted on either side, so the two compare like
+/// for like.
+#[derive(Debug, Clone, Serialize)]
+pub struct CostComparison {
+    pub date: String,
+    pub provider: String,
+    pub estimated_usd: f64,
+    pub billed_usd: f64,
+    /// `billed_usd - estimated_usd`; positive when the estimate ran low.
+    pub delta_usd: f64,
+}
+
+/// Named reporting windows, all ending today.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Today,
+    /// The last seven days, including today.
+    Week,
+    /// The calendar month to date.
+    Month,
+}
+
+impl Period {
+    pub fn range(self, today: NaiveDate) -> DateRange {
+        let start = match self {
+            Period::Today => today,
+            Period::Week => today - Duration::days(6),
+            Period::Month => today.with_day(1).unwrap_or(today),
+        };
+        DateRange { start, end: today }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodSummary {
+    pub period: Period,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub cost_usd: f64,
+    pub tokens: i64,
+    pub by_provider: Vec<ProviderTotal>,
+}
+
+/// Claude Code totals for one profile (config directory) within a range.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileTotal {
+    pub profile: String,
+    pub cost_usd: f64,
+    pub tokens: i64,
+    pub sessions: i64,
+}
+
+/// Claude Code totals for one project within a range. A project used from
+/// several profiles is counted once per profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectTotal {
+    pub project: String,
+    pub profile: String,
+    pub cost_usd: f64,
+    pub tokens: i64,
+    pub sessions: i64,
+    /// Last day in the range the project saw usage.
+    pub last_date: NaiveDate,
+}
+
+/// Claude Code totals for one session within a range.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTotal {
+    pub session_id: String,
+    pub project: String,
+    pub profile: String,
+    pub cost_usd: f64,
+    pub tokens: i64,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+}
+
+/// A historical import in progress. Days from `start` up to (but excluding)
+/// `next` are already stored; the row is deleted once the job passes `end`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillJob {
+    pub provider: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub next: NaiveDate,
+}
+
+fn date_bounds(range: DateRange) -> (String, String) {
+    (
+        range.start.format("%Y-%m-%d").to_string(),
+        range.end.format("%Y-%m-%d").to_string(),
+    )
+}
+
+// A billed amount, once known, survives later upserts that lack one (e.g.
+// when the cost report was unavailable on that poll).
+const UPSERT_SQL: &str = r#"
+INSERT INTO usage_snapshots
+  (provider, model, date, input_tokens, output_tokens, cache_tokens,
+   cache_creation_5m_tokens, cache_creation_1h_tokens, cost_usd, fetched_at, unpriced,
+   estimated_cost_usd, billed_cost_usd, usage_kind, unit, quantity)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+ON CONFLICT(provider, model, date, usage_kind) DO UPDATE SET
+  input_tokens = excluded.input_tokens,
+  output_tokens = excluded.output_tokens,
+  cache_tokens = excluded.cache_tokens,
+  cache_creation_5m_tokens = excluded.cache_creation_5m_tokens,
+  cache_creation_1h_tokens = excluded.cache_creation_1h_tokens,
+  cost_usd = COALESCE(excluded.billed_cost_usd, usage_snapshots.billed_cost_usd, excluded.cost_usd),
+  fetched_at = excluded.fetched_at,
+  unpriced = excluded.unpriced,
+  estimated_cost_usd = excluded.estimated_cost_usd,
+  billed_cost_usd = COALESCE(excluded.billed_cost_usd, usage_snapshots.billed_cost_usd),
+  unit = excluded.unit,
+  quantity = excluded.quantity
+"#;
+
+const UPSERT_HOURLY_SQL: &str = r#"
+INSERT INTO usage_hourly
+  (provider, model, hour, usage_kind, input_tokens, output_tokens, cache_tokens,
+   cache_creation_5m_tokens, cache_creation_1h_tokens, quantity, cost_usd, unpriced, fetched_at)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+ON CONFLICT(provider, model, hour, usage_kind) DO UPDATE SET
+  input_tokens = excluded.input_tokens,
+  output_tokens = excluded.output_tokens,
+  cache_tokens = excluded.cache_tokens,
+  cache_creation_5m_tokens = excluded.cache_creation_5m_tokens,
+  cache_creation_1h_tokens = excluded.cache_creation_1h_tokens,
+  quantity = excluded.quantity,
+  cost_usd = excluded.cost_usd,
+  unpriced = excluded.unpriced,
+  fetched_at = excluded.fetched_at
+"#;
+
+const UPSERT_SESSION_SQL: &str = r#"
+INSERT INTO claude_code_sessions (session_id, project, updated_at)
+VALUES (?1, ?2, ?3)
+ON CONFLICT(session_id) DO UPDATE SET
+  project = excluded.project,
+  updated_at = excluded.updated_at
+"#;
+
+const UPSERT_SESSION_USAGE_SQL: &str = r#"
+INSERT INTO claude_code_session_usage
+  (session_id, model, date, input_tokens, output_tokens, cache_tokens,
+   cache_creation_5m_tokens, cache_creation_1h_tokens, cost_usd, unpriced, fetched_at)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+ON CONFLICT(session_id, model, date) DO UPDATE SET
+  input_tokens = excluded.input_tokens,
+  output_tokens = excluded.output_tokens,
+  cache_tokens = excluded.cache_tokens,
+  cache_creation_5m_tokens = excluded.cache_creation_5m_tokens,
+  cache_creation_1h_tokens = excluded.cache_creation_1h_tokens,
+  cost_usd = excluded.cost_usd,
+  unpriced = excluded.unpriced,
+  fetched_at = excluded.fetched_at
+"#;
+
+// A project read from a log's `cwd` replaces one guessed from its directory
+const INGEST_SESSION_SQL: &str = r#"
+INSERT INTO claude_code_sessions (session_id, project, profile, updated_at)
+VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT(session_id) DO UPDATE SET
+  project = CASE WHEN ?5 THEN claude_code_sessions.project ELSE excluded.project END,
+  profile = excluded.profile,
+  updated_at = excluded.updated_at
+"#;
+
+// A streamed response is logged over several lines and only the last carries
+// the final output count, so the copy with the most output wins. The session
+// and hour stay those of the first copy seen.
+const UPSERT_EVENT_SQL: &str = r#"
+INSERT INTO claude_code_events
+  (message_id, request_id, session_id, subagent, model, hour, input_tokens, output_tokens,
+   cache_tokens, cache_creation_5m_tokens, cache_creation_1h_tokens)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+ON CONFLICT(message_id, request_id) DO UPDATE SET
+  input_tokens = excluded.input_tokens,
+  output_tokens = excluded.output_tokens,
+  cache_tokens = excluded.cache_tokens,
+  cache_creation_5m_tokens = excluded.cache_creation_5m_tokens,
+  cache_creation_1h_tokens = excluded.cache_creation_1h_tokens
+WHERE excluded.output_tokens > claude_code_events.output_tokens
+"#;
+
+const SELECT_HOURLY_COLUMNS: &str = "provider, model, hour, usage_kind, input_tokens, \
+     output_tokens, cache_tokens, cache_creation_5m_tokens, cache_creation_1h_tokens, quantity, \
+     cost_usd, unpriced, fetched_at";
+
+const SELECT_COLUMNS: &str = "id, provider, model, date, input_tokens, output_tokens, \
+     cache_tokens, cache_creation_5m_tokens, cache_creation_1h_tokens, cost_usd, fetched_at, \
+     unpriced, estimated_cost_usd, billed_cost_usd, usage_kind, quantity";
+
+/// Handle to the usage database. Cheap to clone; every clone shares one
+/// connection, and queries run on the blocking thread pool.
+#[derive(Clone)]
+pub struct Storage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Storage {
+    /// Open (creating if needed) the database at `path` and bring its schema
+    /// up to date.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut conn = Connection::open(path).map_err(|e| format!("Cannot open database: {e}"))?;
+        migrate(&mut conn, path)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+    }
+
+    /// Insert or replace snapshots, keyed by `(provider, model, date)`.
+    pub async fn upsert_snapshots(&self, snapshots: Vec<UsageSnapshot>) -> Result<(), String> {
+        if snapshots.is_empty() {
+            return Ok(());
+        }
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            write_snapshots(&tx, &snapshots)?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Insert or replace hourly rows, keyed by `(provider, model, hour, kind)`.
+    pub async fn upsert_hourly(&self, rows: Vec<HourlyUsage>) -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            write_hourly(&tx, &rows)?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Insert or replace per-session rows, keyed by `(session, model, date)`,
+    /// and record each session's project.
+    pub async fn upsert_sessions(&self, rows: Vec<SessionUsage>) -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            write_sessions(&tx, &rows)?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Re-cost every stored snapshot, hourly row and session at the prices in
+    /// effect on its date. This runs in one transaction so a poll storing rows
+    /// at the same time cannot have them overwritten with stale usage.
+    /// Returns how many snapshots were repriced.
+    pub async fn reprice_all(&self) -> Result<usize, String> {
+        self.call(|conn| {
+            let tx = conn.transaction()?;
+            let mut snapshots = select_snapshots(&tx, "")?;
+            snapshots.iter_mut().for_each(pricing::price_snapshot);
+            write_snapshots(&tx, &snapshots)?;
+            let mut hourly = select_hourly(&tx)?;
+            hourly.iter_mut().for_each(pricing::price_hourly);
+            write_hourly(&tx, &hourly)?;
+            let mut sessions = select_sessions(&tx)?;
+            sessions.iter_mut().for_each(pricing::price_session);
+            write_sessions(&tx, &sessions)?;
+            tx.commit()?;
+            Ok(snapshots.len())
+        })
+        .await
+    }
+
+    /// Where each Claude Code log was last read up to, keyed by path.
+    pub async fn log_cursors(&self) -> Result<HashMap<String, LogCursor>, String> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, inode, size, byte_offset, last_line_len, last_line_hash
+                 FROM claude_code_log_cursors",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(LogCursor {
+                    path: row.get(0)?,
+                    inode: row.get::<_, i64>(1)? as u64,
+                    size: row.get::<_, i64>(2)? as u64,
+                    offset: row.get::<_, i64>(3)? as u64,
+                    last_line_len: row.get::<_, i64>(4)? as u64,
+                    last_line_hash: row.get(5)?,
+                })
+            })?;
+            rows.map(|r| r.map(|c| (c.path.clone(), c))).collect()
+        })
+        .await
+    }
+
+    /// Forget where the logs at `paths` were read up to.
+    pub async fn delete_log_cursors(&self, paths: Vec<String>) -> Result<(), String> {
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt =
+                    tx.prepare_cached("DELETE FROM claude_code_log_cursors WHERE path = ?1")?;
+                for path in &paths {
+                    stmt.execute(params![path])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Forget every log cursor and the responses read through them, so
+    /// every Claude Code log is read again from the start.
+    pub async fn clear_log_cursors(&self) -> Result<(), String> {
+        self.call(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute_batch(
+                "DELETE FROM claude_code_log_cursors;
+                 DELETE FROM claude_code_events;",
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Store a log's new events and move its cursor past them in one
+    /// transaction, so a line is never counted twice or skipped. Returns how
+    /// many events were already stored from another copy or an earlier line.
+    pub async fn ingest_log(&self, batch: LogBatch) -> Result<u64, String> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let mut duplicates = 0;
+            {
+                let mut exists_stmt = tx.prepare_cached(
+                    "SELECT 1 FROM claude_code_events WHERE message_id = ?1 AND request_id = ?2",
+                )?;
+                let mut stmt = tx.prepare_cached(UPSERT_EVENT_SQL)?;
+                for e in &batch.events {
+                    let stored = exists_stmt
+                        .query_row(params![e.message_id, e.request_id], |_| Ok(()))
+                        .optional()?
+                        .is_some();
+                    if stored {
+                        duplicates += 1;
+                    }
+                    stmt.execute(params![
+                        e.message_id,
+                        e.request_id,
+                        e.session_id,
+                        e.subagent,
+                        e.model,
+                        e.hour,
+                        e.input_tokens,
+                        e.output_tokens,
+                        e.cache_tokens,
+                        e.cache_creation_5m_tokens,
+                        e.cache_creation_1h_tokens,
+                    ])?;
+                }
+            }
+            tx.execute(
+                INGEST_SESSION_SQL,
+                params![
+                    batch.session_id,
+                    batch.project,
+                    batch.profile,
+                    now,
+                    batch.project_guessed,
+                ],
+            )?;
+            let c = &batch.cursor;
+            tx.execute(
+                "INSERT OR REPLACE INTO claude_code_log_cursors
+                   (path, inode, size, byte_offset, last_line_len, last_line_hash, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    c.path,
+                    c.inode as i64,
+                    c.size as i64,
+                    c.offset as i64,
+                    c.last_line_len as i64,
+                    c.last_line_hash,
+                    now,
+                ],
+            )?;
+            tx.commit()?;
+            Ok(duplicates)
+        })
+        .await
+    }
+
+    /// Claude Code usage logged in hours from `start_hour` up to (excluding)
+    /// `end_hour`, summed per hour, model, session and subagent flag.
+    pub async fn claude_code_usage(
+        &self,
+        start_hour: String,
+        end_hour: String,
+    ) -> Result<Vec<ClaudeCodeUsage>, String> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT e.hour, e.model, e.session_id, COALESCE(s.project, ''), e.subagent,
+                        SUM(e.input_tokens), SUM(e.output_tokens), SUM(e.cache_tokens),
+                        SUM(e.cache_creation_5m_tokens), SUM(e.cache_creation_1h_tokens)
+                 FROM claude_code_events e
+                 LEFT JOIN claude_code_sessions s ON s.session_id = e.session_id
+                 WHERE e.hour >= ?1 AND e.hour < ?2
+                 GROUP BY e.hour, e.model, e.session_id, e.subagent",
+            )?;
+            let rows = stmt.query_map([start_hour, end_hour], |row| {
+                Ok(ClaudeCodeUsage {
+                    hour: row.get(0)?,
+                    model: row.get(1)?,
+                    session_id: row.get(2)?,
+                    project: row.get(3)?,
+                    subagent: row.get(4)?,
+                    input_tokens: row.get(5)?,
+                    output_tokens: row.get(6)?,
+                    cache_tokens: row.get(7)?,
+                    cache_creation_5m_tokens: row.get(8)?,
+                    cache_creation_1h_tokens: row.get(9)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Every snapshot dated on or after `since` (`YYYY-MM-DD`), oldest first.
+    pub async fn snapshots_since(&self, since: String) -> Result<Vec<UsageSnapshot>, String> {
+        self.call(move |conn| select_snapshots(conn, &since)).await
+    }
+
+    /// Per-day cost within `range`, broken down by `group_by`, oldest first.
+    /// Days without any usage are omitted.
+    pub async fn daily_totals(
+        &self,
+        range: DateRange,
+        group_by: GroupBy,
+    ) -> Result<Vec<DailyTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let sql = format!(
+                "SELECT date, {col}, SUM(cost_usd), SUM(input_tokens + output_tokens)
+                 FROM usage_snapshots
+                 WHERE date BETWEEN ?1 AND ?2
+                 GROUP BY date, {col}
+                 ORDER BY date ASC",
+                col = group_by.column()
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query([start, end])?;
+            let mut days: Vec<DailyTotal> = Vec::new();
+            while let Some(row) = rows.next()? {
+                let date: String = row.get(0)?;
+                let group: String = row.get(1)?;
+                let cost: f64 = row.get(2)?;
+                let tokens: i64 = row.get(3)?;
+                if days.last().is_none_or(|d| d.date != date) {
+                    days.push(DailyTotal {
+                        date,
+                        total: 0.0,
+                        tokens: 0,
+                        groups: BTreeMap::new(),
+                    });
+                }
+                let day = days.last_mut().expect("pushed above");
+                day.total += cost;
+                day.tokens += tokens;
+                *day.groups.entry(group).or_default() += cost;
+            }
+            Ok(days)
+        })
+        .await
+    }
+
+    /// Every hour of `date` as it falls in `tz` (23 or 25 across a DST
+    /// change), broken down by `group_by`. Hours without usage are included
+    /// with zero totals so the timeline has no gaps.
+    pub async fn hourly_timeline(
+        &self,
+        date: NaiveDate,
+        tz: Tz,
+        group_by: GroupBy,
+    ) -> Result<Vec<HourlyTotal>, String> {
+        // Stored hours are whole UTC hours. Where the zone is offset by part
+        // of an hour they straddle midnight, and like in the daily roll-up
+        // each counts towards the day it starts on.
+        let start = timezone::day_start(date, tz);
+        let end = timezone::day_start(date + Duration::days(1), tz);
+        let mut hours: Vec<HourlyTotal> = Vec::new();
+        let mut hour = start
+            .duration_trunc(Duration::hours(1))
+            .map_err(|e| e.to_string())?;
+        while hour < end {
+            if timezone::local_date(hour, tz) == date {
+                hours.push(HourlyTotal {
+                    hour: hour.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    total: 0.0,
+                    tokens: 0,
+                    groups: BTreeMap::new(),
+                });
+            }
+            hour += Duration::hours(1);
+        }
+        let (first, last) = match (hours.first(), hours.last()) {
+            (Some(first), Some(last)) => (first.hour.clone(), last.hour.clone()),
+            _ => return Ok(hours),
+        };
+        self.call(move |conn| {
+            let sql = format!(
+                "SELECT hour, {col}, SUM(cost_usd), SUM(input_tokens + output_tokens)
+                 FROM usage_hourly
+                 WHERE hour BETWEEN ?1 AND ?2
+                 GROUP BY hour, {col}",
+                col = group_by.column()
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query([first, last])?;
+            while let Some(row) = rows.next()? {
+                let hour: String = row.get(0)?;
+                let Some(slot) = hours.iter_mut().find(|h| h.hour == hour) else {
+                    continue;
+                };
+                let cost: f64 = row.get(2)?;
+                slot.total += cost;
+                slot.tokens += row.get::<_, i64>(3)?;
+                *slot.groups.entry(row.get(1)?).or_default() += cost;
+            }
+            Ok(hours)
+        })
+        .await
+    }
+
+    /// Totals per `(provider, model)` within `range`, most expensive first.
+    pub async fn model_totals(&self, range: DateRange) -> Result<Vec<ModelTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT provider, model, SUM(input_tokens), SUM(output_tokens), SUM(cost_usd)
+                 FROM usage_snapshots
+                 WHERE date BETWEEN ?1 AND ?2
+                 GROUP BY provider, model
+                 ORDER BY SUM(cost_usd) DESC",
+            )?;
+            let rows = stmt.query_map([start, end], |row| {
+                let input_tokens: i64 = row.get(2)?;
+                let output_tokens: i64 = row.get(3)?;
+                Ok(ModelTotal {
+                    provider: row.get(0)?,
+                    model: row.get(1)?,
+                    input_tokens,
+                    output_tokens,
+                    total_tokens: input_tokens + output_tokens,
+                    cost_usd: row.get(4)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Totals per provider within `range`, most expensive first.
+    pub async fn provider_totals(&self, range: DateRange) -> Result<Vec<ProviderTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT provider, SUM(cost_usd), SUM(input_tokens + output_tokens)
+                 FROM usage_snapshots
+                 WHERE date BETWEEN ?1 AND ?2
+                 GROUP BY provider
+                 ORDER BY SUM(cost_usd) DESC",
+            )?;
+            let rows = stmt.query_map([start, end], |row| {
+                Ok(ProviderTotal {
+                    provider: row.get(0)?,
+                    cost_usd: row.get(1)?,
+                    tokens: row.get(2)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Claude Code totals per profile within `range`, costliest first.
+    pub async fn profile_totals(&self, range: DateRange) -> Result<Vec<ProfileTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT s.profile, SUM(u.cost_usd), SUM(u.input_tokens + u.output_tokens),
+                        COUNT(DISTINCT u.session_id)
+                 FROM claude_code_session_usage u
+                 JOIN claude_code_sessions s ON s.session_id = u.session_id
+                 WHERE u.date BETWEEN ?1 AND ?2
+                 GROUP BY s.profile
+                 ORDER BY SUM(u.cost_usd) DESC",
+            )?;
+            let rows = stmt.query_map([start, end], |row| {
+                Ok(ProfileTotal {
+                    profile: row.get(0)?,
+                    cost_usd: row.get(1)?,
+                    tokens: row.get(2)?,
+                    sessions: row.get(3)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// The `limit` Claude Code projects that cost the most within `range`.
+    pub async fn top_projects(
+        &self,
+        range: DateRange,
+        limit: usize,
+    ) -> Result<Vec<ProjectTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT s.project, s.profile, SUM(u.cost_usd),
+                        SUM(u.input_tokens + u.output_tokens), COUNT(DISTINCT u.session_id),
+                        MAX(u.date)
+                 FROM claude_code_session_usage u
+                 JOIN claude_code_sessions s ON s.session_id = u.session_id
+                 WHERE u.date BETWEEN ?1 AND ?2
+                 GROUP BY s.project, s.profile
+                 ORDER BY SUM(u.cost_usd) DESC
+                 LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![start, end, limit as i64], |row| {
+                Ok(ProjectTotal {
+                    project: row.get(0)?,
+                    profile: row.get(1)?,
+                    cost_usd: row.get(2)?,
+                    tokens: row.get(3)?,
+                    sessions: row.get(4)?,
+                    last_date: day_column(row, 5)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// The `limit` Claude Code sessions that cost the most within `range`.
+    pub async fn top_sessions(
+        &self,
+        range: DateRange,
+        limit: usize,
+    ) -> Result<Vec<SessionTotal>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT u.session_id, s.project, s.profile, SUM(u.cost_usd),
+                        SUM(u.input_tokens + u.output_tokens), MIN(u.date), MAX(u.date)
+                 FROM claude_code_session_usage u
+                 JOIN claude_code_sessions s ON s.session_id = u.session_id
+                 WHERE u.date BETWEEN ?1 AND ?2
+                 GROUP BY u.session_id
+                 ORDER BY SUM(u.cost_usd) DESC
+                 LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![start, end, limit as i64], |row| {
+                Ok(SessionTotal {
+                    session_id: row.get(0)?,
+                    project: row.get(1)?,
+                    profile: row.get(2)?,
+                    cost_usd: row.get(3)?,
+                    tokens: row.get(4)?,
+                    first_date: day_column(row, 5)?,
+                    last_date: day_column(row, 6)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Per-day estimate vs. billed totals within `range`, oldest first.
+    /// `tz` is the zone bills were assigned to local days in.
+    pub async fn cost_comparison(
+        &self,
+        range: DateRange,
+        tz: Tz,
+    ) -> Result<Vec<CostComparison>, String> {
+        let (start, end) = date_bounds(range);
+        self.call(move |conn| {
+            let mut billed_stmt = conn.prepare(
+                "SELECT date, provider, SUM(billed_cost_usd)
+                 FROM usage_snapshots
+                 WHERE date BETWEEN ?1 AND ?2 AND billed_cost_usd IS NOT NULL
+                 GROUP BY date, provider
+                 ORDER BY date ASC, provider ASC",
+            )?;
+            let mut estimate_stmt = conn.prepare(
+                "SELECT COALESCE(SUM(cost_usd), 0) FROM usage_hourly
+                 WHERE provider = ?1 AND hour >= ?2 AND hour < ?3
+                   AND model IN (SELECT model FROM usage_snapshots
+                                 WHERE provider = ?1 AND date = ?4
+                                   AND billed_cost_usd IS NOT NULL)",
+            )?;
+            let billed = billed_stmt
+                .query_map([start, end], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<rusqlite::Result<Vec<(String, String, f64)>>>()?;
+            billed
+                .into_iter()
+                .map(|(date, provider, billed_usd)| {
+                    let day = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                        .map(|day| billed_utc_day(day, tz))
+                        .unwrap_or_default();
+                    let estimated_usd: f64 = estimate_stmt.query_row(
+                        params![
+                            provider,
+                            day.format("%Y-%m-%dT00:00:00Z").to_string(),
+                            (day + Duration::days(1))
+                                .format("%Y-%m-%dT00:00:00Z")
+                                .to_string(),
+                            date,
+                        ],
+                        |row| row.get(0),
+                    )?;
+                    Ok(CostComparison {
+                        date,
+                        provider,
+                        estimated_usd,
+                        billed_usd,
+                        delta_usd: billed_usd - estimated_usd,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// The last day `provider` has been fetched through and considered
+    /// settled, or `None` if it has never completed a fetch (or was reset).
+    pub async fn watermark(&self, provider: &'static str) -> Result<Option<NaiveDate>, String> {
+        let day: Option<String> = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT complete_through FROM fetch_watermarks WHERE provider = ?1",
+                    [provider],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(day.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()))
+    }
+
+    pub async fn set_watermark(
+        &self,
+        provider: &'static str,
+        complete_through: NaiveDate,
+    ) -> Result<(), String> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO fetch_watermarks (provider, complete_through, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(provider) DO UPDATE SET
+                   complete_through = excluded.complete_through,
+                   updated_at = excluded.updated_at",
+                params![
+                    provider,
+                    complete_through.format("%Y-%m-%d").to_string(),
+                    chrono::Utc::now().to_rfc3339(),
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Forget the watermark for `provider`, or for every provider, so the
+    /// next poll re-fetches the whole history window.
+    pub async fn clear_watermarks(&self, provider: Option<String>) -> Result<(), String> {
+        self.call(move |conn| {
+            match provider {
+                Some(p) => conn.execute("DELETE FROM fetch_watermarks WHERE provider = ?1", [p]),
+                None => conn.execute("DELETE FROM fetch_watermarks", []),
+            }
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Every unfinished backfill, e.g. to resume them after a restart.
+    pub async fn backfill_jobs(&self) -> Result<Vec<BackfillJob>, String> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT provider, start_date, end_date, next_date FROM backfill_jobs
+                 ORDER BY provider",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(BackfillJob {
+                    provider: row.get(0)?,
+                    start: day_column(row, 1)?,
+                    end: day_column(row, 2)?,
+                    next: day_column(row, 3)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Record how far `job` has got, replacing any other job for its provider.
+    pub async fn save_backfill_job(&self, job: BackfillJob) -> Result<(), String> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO backfill_jobs
+                   (provider, start_date, end_date, next_date, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    job.provider,
+                    job.start.format("%Y-%m-%d").to_string(),
+                    job.end.format("%Y-%m-%d").to_string(),
+                    job.next.format("%Y-%m-%d").to_string(),
+                    chrono::Utc::now().to_rfc3339(),
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    pub async fn delete_backfill_job(&self, provider: String) -> Result<(), String> {
+        self.call(move |conn| {
+            conn.execute("DELETE FROM backfill_jobs WHERE provider = ?1", [provider])
+                .map(|_| ())
+        })
+        .await
+    }
+
+    /// Cost and tokens for `period`, ending on `today`.
+    pub async fn period_summary(
+        &self,
+        period: Period,
+        today: NaiveDate,
+    ) -> Result<PeriodSummary, String> {
+        let range = period.range(today);
+        let by_provider = self.provider_totals(range).await?;
+        Ok(PeriodSummary {
+            period,
+            start: range.start,
+            end: range.end,
+            cost_usd: by_provider.iter().map(|p| p.cost_usd).sum(),
+            tokens: by_provider.iter().map(|p| p.tokens).sum(),
+            by_provider,
+        })
+    }
+}
+
+/// The UTC day whose bill [`timezone::bucket_date`] assigned to local day
+/// `date`.
+fn billed_utc_day(date: NaiveDate, tz: Tz) -> NaiveDate {
+    [date, date - Duration::days(1), date + Duration::days(1)]
+        .into_iter()
+        .find(|day| timezone::bucket_date(timezone::day_start(*day, Tz::UTC), tz) == date)
+        .unwrap_or(date)
+}
+
+fn day_column(row: &Row, idx: usize) -> rusqlite::Result<NaiveDate> {
+    let text: String = row.get(idx)?;
+    NaiveDate::parse_from_str(&text, "%Y-%m-%d").map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn write_snapshots(tx: &Transaction, snapshots: &[UsageSnapshot]) -> rusqlite::Result<()> {
+    let mut stmt = tx.prepare_cached(UPSERT_SQL)?;
+    for s in snapshots {
+        stmt.execute(params![
+            s.provider,
+            s.model,
+            s.date,
+            s.input_tokens,
+            s.output_tokens,
+            s.cache_tokens,
+            s.cache_creation_5m_tokens,
+            s.cache_creation_1h_tokens,
+            s.cost_usd,
+            s.fetched_at,
+            s.unpriced,
+            s.estimated_cost_usd,
+            s.billed_cost_usd,
+            s.usage_kind.as_str(),
+            s.unit.as_str(),
+            s.quantity,
+        ])?;
+    }
+    Ok(())
+}
+
+fn write_hourly(tx: &Transaction, rows: &[HourlyUsage]) -> rusqlite::Result<()> {
+    let mut stmt = tx.prepare_cached(UPSERT_HOURLY_SQL)?;
+    for r in rows {
+        stmt.execute(params![
+            r.provider,
+            r.model,
+            r.hour,
+            r.usage_kind.as_str(),
+            r.input_tokens,
+            r.output_tokens,
+            r.cache_tokens,
+            r.cache_creation_5m_tokens,
+            r.cache_creation_1h_tokens,
+            r.quantity,
+            r.cost_usd,
+            r.unpriced,
+            r.fetched_at,
+        ])?;
+    }
+    Ok(())
+}
+
+fn write_sessions(tx: &Transaction, rows: &[SessionUsage]) -> rusqlite::Result<()> {
+    let mut session_stmt = tx.prepare_cached(UPSERT_SESSION_SQL)?;
+    let mut usage_stmt = tx.prepare_cached(UPSERT_SESSION_USAGE_SQL)?;
+    for r in rows {
+        session_stmt.execute(params![r.session_id, r.project, r.fetched_at])?;
+        usage_stmt.execute(params![
+            r.session_id,
+            r.model,
+            r.date,
+            r.input_tokens,
+            r.output_tokens,
+            r.cache_tokens,
+            r.cache_creation_5m_tokens,
+            r.cache_creation_1h_tokens,
+            r.cost_usd,
+            r.unpriced,
+            r.fetched_at,
+        ])?;
+    }
+    Ok(())
+}
+
+fn select_snapshots(conn: &Connection, since: &str) -> rusqlite::Result<Vec<UsageSnapshot>> {
+    let sql =
+        format!("SELECT {SELECT_COLUMNS} FROM usage_snapshots WHERE date >= ?1 ORDER BY date ASC");
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map([since], row_to_snapshot)?;
+    rows.collect()
+}
+
+fn select_hourly(conn: &Connection) -> rusqlite::Result<Vec<HourlyUsage>> {
+    let sql = format!("SELECT {SELECT_HOURLY_COLUMNS} FROM usage_hourly ORDER BY hour ASC");
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map([], row_to_hourly)?;
+    rows.collect()
+}
+
+fn select_sessions(conn: &Connection) -> rusqlite::Result<Vec<SessionUsage>> {
+    let mut stmt = conn.prepare(
+        "SELECT u.session_id, s.project, u.model, u.date, u.input_tokens, u.output_tokens,
+                u.cache_tokens, u.cache_creation_5m_tokens, u.cache_creation_1h_tokens,
+                u.cost_usd, u.unpriced, u.fetched_at
+         FROM claude_code_session_usage u
+         JOIN claude_code_sessions s ON s.session_id = u.session_id
+         ORDER BY u.date ASC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SessionUsage {
+            session_id: row.get(0)?,
+            project: row.get(1)?,
+            model: row.get(2)?,
+            date: row.get(3)?,
+            input_tokens: row.get(4)?,
+            output_tokens: row.get(5)?,
+            cache_tokens: row.get(6)?,
+            cache_creation_5m_tokens: row.get(7)?,
+            cache_creation_1h_tokens: row.get(8)?,
+            cost_usd: row.get(9)?,
+            unpriced: row.get(10)?,
+            fetched_at: row.get(11)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn row_to_hourly(row: &Row) -> rusqlite::Result<HourlyUsage> {
+    Ok(HourlyUsage {
+        provider: row.get(0)?,
+        model: row.get(1)?,
+        hour: row.get(2)?,
+        usage_kind: UsageKind::parse(&row.get::<_, String>(3)?),
+        input_tokens: row.get(4)?,
+        output_tokens: row.get(5)?,
+        cache_tokens: row.get(6)?,
+        cache_creation_5m_tokens: row.get(7)?,
+        cache_creation_1h_tokens: row.get(8)?,
+        quantity: row.get(9)?,
+        cost_usd: row.get(10)?,
+        unpriced: row.get(11)?,
+        fetched_at: row.get(12)?,
+    })
+}
+
+fn row_to_snapshot(row: &Row) -> rusqlite::Result<UsageSnapshot> {
+    let usage_kind = UsageKind::parse(&row.get::<_, String>(14)?);
+    Ok(UsageSnapshot {
+        id: row.get(0)?,
+        provider: row.get(1)?,
+        model: row.get(2)?,
+        date: row.get(3)?,
+        input_tokens: row.get(4)?,
+        output_tokens: row.get(5)?,
+        cache_tokens: row.get(6)?,
+        cache_creation_5m_tokens: row.get(7)?,
+        cache_creation_1h_tokens: row.get(8)?,
+        cost_usd: row.get(9)?,
+        fetched_at: row.get(10)?,
+        unpriced: row.get(11)?,
+        estimated_cost_usd: row.get(12)?,
+        billed_cost_usd: row.get(13)?,
+        usage_kind,
+        unit: usage_kind.unit(),
+        quantity: row.get(15)?,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A path under the temp dir that no other test uses, with nothing at it,
+    /// ending in `name`.
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("token-tracker-test-{}-{n}-{name}", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    pub(crate) fn temp_db_path() -> PathBuf {
+        temp_path("usage.db")
+    }
+
+    /// An empty directory under the temp dir that no other test uses.
+    pub(crate) fn temp_dir() -> PathBuf {
+        let dir = temp_path("dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    pub(crate) fn temp_storage() -> Storage {
+        Storage::open(&temp_db_path()).unwrap()
+    }
+
+    fn latest_version() -> i64 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    #[tokio::test]
+    async fn migrates_a_v1_database_after_backing_it_up() {
+        let path = temp_db_path();
+        {
+            let mut conn = Connection::open(&path).unwrap();
+            let tx = conn.transaction().unwrap();
+            tx.execute_batch(V1_SQL).unwrap();
+            schema_version(&tx).unwrap();
+            tx.execute_batch(
+                "INSERT INTO schema_version VALUES (1, 'initial schema', '2025-01-01T00:00:00Z');
+                 INSERT INTO usage_snapshots
+                   (provider, model, date, input_tokens, output_tokens, cost_usd, fetched_at)
+                 VALUES ('openai', 'gpt-4o', '2025-01-01', 1000, 500, 0.5, '2025-01-01T00:00:00Z');",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let storage = Storage::open(&path).unwrap();
+        let version = storage.call(|conn| schema_version(conn)).await.unwrap();
+        assert_eq!(version, latest_version());
+        let rows = storage.snapshots_since(String::new()).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].input_tokens, rows[0].output_tokens), (1000, 500));
+        assert_eq!(rows[0].estimated_cost_usd, 0.5);
+        assert_eq!(rows[0].billed_cost_usd, None);
+        assert_eq!(rows[0].usage_kind, UsageKind::Text);
+
+        let backup = PathBuf::from(format!("{}.pre-v{}.bak", path.display(), latest_version()));
+        let old = Connection::open(&backup).unwrap();
+        assert_eq!(schema_version(&old).unwrap(), 1);
+        drop(old);
+        drop(storage);
+
+        // Nothing left to migrate, so no new backup
+        std::fs::remove_file(&backup).unwrap();
+        drop(Storage::open(&path).unwrap());
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn creates_a_fresh_database_without_a_backup() {
+        let path = temp_db_path();
+        drop(Storage::open(&path).unwrap());
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        let backup = format!("{}.pre-v{}.bak", path.display(), latest_version());
+        assert!(!Path::new(&backup).exists());
+    }
+
+    #[test]
+    fn refuses_a_schema_from_a_newer_build() {
+        let path = temp_db_path();
+        drop(Storage::open(&path).unwrap());
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "INSERT INTO schema_version VALUES (?1, 'from the future', '2030-01-01T00:00:00Z')",
+                [latest_version() + 1],
+            )
+            .unwrap();
+        assert!(Storage::open(&path).is_err());
+    }
+
+    fn snapshot(model: &str, date: &str, billed_cost_usd: Option<f64>) -> UsageSnapshot {
+        UsageSnapshot {
+            id: None,
+            provider: "openai".to_string(),
+            model: model.to_string(),
+            date: date.to_string(),
+            input_tokens: 0,
+            output_tokens: 0,
+            cache_tokens: 0,
+            cache_creation_5m_tokens: 0,
+            cache_creation_1h_tokens: 0,
+            cost_usd: 0.0,
+            estimated_cost_usd: 0.0,
+            billed_cost_usd,
+            usage_kind: UsageKind::Text,
+            unit: Unit::Tokens,
+            quantity: 0.0,
+            fetched_at: String::new(),
+            unpriced: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn compares_a_bill_with_the_estimate_for_its_utc_day() {
+        let storage = temp_storage();
+        let billed = snapshot("gpt-4o", "2025-03-01", Some(2.5));
+        let unbilled = snapshot("gpt-4o-mini", "2025-03-01", None);
+        let hour = |snapshot: &UsageSnapshot, hour: &str, cost_usd| HourlyUsage {
+            cost_usd,
+            ..HourlyUsage::new(hour.to_string(), snapshot)
+        };
+        storage
+            .upsert_hourly(vec![
+                hour(&billed, "2025-03-01T00:00:00Z", 1.0),
+                hour(&billed, "2025-03-01T23:00:00Z", 0.5),
+                // The next UTC day, though still 2025-03-02 in Tokyo
+                hour(&billed, "2025-03-02T01:00:00Z", 9.0),
+                // Models without a bill are left out of both sides
+                hour(&unbilled, "2025-03-01T05:00:00Z", 7.0),
+            ])
+            .await
+            .unwrap();
+        storage
+            .upsert_snapshots(vec![billed, unbilled])
+            .await
+            .unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let range = DateRange {
+            start: day,
+            end: day + Duration::days(1),
+        };
+        let rows = storage
+            .cost_comparison(range, "Asia/Tokyo".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].date, "2025-03-01");
+        assert_eq!(rows[0].provider, "openai");
+        assert_eq!((rows[0].estimated_usd, rows[0].billed_usd), (1.5, 2.5));
+        assert_eq!(rows[0].delta_usd, 1.0);
+    }
+
+    #[tokio::test]
+    async fn ranks_projects_and_sessions_by_cost_within_the_range() {
+        let storage = temp_storage();
+        let session = |session_id: &str, project: &str, date: &str, cost_usd| SessionUsage {
+            session_id: session_id.to_string(),
+            project: project.to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            date: date.to_string(),
+            input_tokens: 10,
+            output_tokens: 5,
+            cache_tokens: 0,
+            cache_creation_5m_tokens: 0,
+            cache_creation_1h_tokens: 0,
+            cost_usd,
+            unpriced: false,
+            fetched_at: String::new(),
+        };
+        storage
+            .upsert_sessions(vec![
+                session("s1", "/home/me/app", "2025-03-01", 1.0),
+                session("s1", "/home/me/app", "2025-03-02", 2.0),
+                session("s2", "/home/me/app", "2025-03-02", 0.5),
+                session("s3", "/home/me/api", "2025-03-03", 1.0),
+                // After the range, where it would have ranked first
+                session("s3", "/home/me/api", "2025-03-04", 9.0),
+            ])
+            .await
+            .unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let range = DateRange {
+            start: day,
+            end: day + Duration::days(2),
+        };
+        let projects = storage.top_projects(range, 10).await.unwrap();
+        let projects: Vec<_> = projects
+            .iter()
+            .map(|p| (p.project.as_str(), p.cost_usd, p.sessions, p.last_date))
+            .collect();
+        assert_eq!(
+            projects,
+            [
+                ("/home/me/app", 3.5, 2, day + Duration::days(1)),
+                ("/home/me/api", 1.0, 1, day + Duration::days(2)),
+            ]
+        );
+
+        let sessions = storage.top_sessions(range, 2).await.unwrap();
+        let sessions: Vec<_> = sessions
+            .iter()
+            .map(|s| (s.session_id.as_str(), s.cost_usd, s.tokens, s.first_date))
+            .collect();
+        assert_eq!(sessions, [("s1", 3.0, 30, day), ("s3", 1.0, 15, range.end)]);
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+/// The zone named by the `timezone` setting, falling back to the system zone
+/// and then UTC. Days, "today" and provider day boundaries all use it.
+pub fn from_settings(settings: &serde_json::Map<String, Value>) -> Tz {
+    let configured = settings
+        .get("timezone")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    if let Some(name) = configured {
+        match name.parse() {
+            Ok(tz) => return tz,
+            Err(_) => log::warn!("unknown timezone {name:?}, using the system zone"),
+        }
+    }
+    system()
+}
+
+fn system() -> Tz {
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// The instant `date` starts in `tz`. Where midnight falls in a DST gap the
+/// day starts at the first valid time after it.
+pub fn day_start(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let mut local = date.and_time(NaiveTime::MIN);
+    loop {
+        if let Some(start) = tz.from_local_datetime(&local).earliest() {
+            return start.with_timezone(&Utc);
+        }
+        local += Duration::hours(1);
+    }
+}
+
+/// The local day an instant falls on.
+pub fn local_date(instant: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    instant.with_timezone(&tz).date_naive()
+}
+
+/// The local day a provider's daily bucket starting at `start` belongs to.
+/// Buckets may be aligned to UTC or to the requested local midnight; taking
+/// the day at the bucket's midpoint gives the day most of it overlaps either
+/// way.
+pub fn bucket_date(start: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    local_date(start + Duration::hours(12), tz)
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Runtime};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::api::claude_code::{self, ClaudeCodeProvider};
+use crate::poller;
+
+/// Quiet time after a write before the logs are read, so a burst of lines
+/// is picked up in one go.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// Longest a read is put off while an agent keeps writing.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+/// How often to look for config directories that were added to the settings
+/// or that Claude Code has only now created.
+const RESCAN: Duration = Duration::from_secs(60);
+
+/// Watch Claude Code's session logs and fetch today's Claude Code usage
+/// shortly after they change, rather than waiting for the next poll.
+pub fn start_log_watcher<R: Runtime + 'static>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // Dropping the watcher stops it, so it lives as long as this task
+        let mut watcher = match log_watcher(tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log::warn!("cannot watch Claude Code logs: {e}");
+                return;
+            }
+        };
+        let mut watched = HashSet::new();
+        let mut rescan = tokio::time::interval(RESCAN);
+
+        loop {
+            tokio::select! {
+                _ = rescan.tick() => watch_new_roots(&app, &mut watcher, &mut watched).await,
+                Some(()) = rx.recv() => {
+                    let deadline = Instant::now() + MAX_DELAY;
+                    loop {
+                        let wait_until = deadline.min(Instant::now() + DEBOUNCE);
+                        match tokio::time::timeout_at(wait_until, rx.recv()).await {
+                            Ok(Some(())) => continue,
+                            Ok(None) => return,
+                            Err(_) => break,
+                        }
+                    }
+                    poller::refresh_today(&app, &ClaudeCodeProvider).await;
+                }
+            }
+        }
+    });
+}
+
+/// Start watching the `projects` directory of every config directory that
+/// has one and is not watched yet.
+async fn watch_new_roots<R: Runtime>(
+    app: &AppHandle<R>,
+    watcher: &mut RecommendedWatcher,
+    watched: &mut HashSet<PathBuf>,
+) {
+    let settings = poller::read_settings(app).await;
+    let ctx = poller::fetch_context(app, &settings);
+    for root in claude_code::log_roots(&ctx.home_dir, &ctx.settings) {
+        let dir = root.projects_dir();
+        if !root.found || watched.contains(&dir) {
+            continue;
+        }
+        // Not retried on failure, the regular poll still reads the logs
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::Recursive) {
+            log::warn!("cannot watch {}: {e}", dir.display());
+        }
+        watched.insert(dir);
+    }
+}
+
+/// A watcher that sends on `tx` whenever a `.jsonl` file under a watched
+/// directory is created or written.
+fn log_watcher(tx: mpsc::UnboundedSender<()>) -> notify::Result<RecommendedWatcher> {
+    notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else { return };
+        let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+        let is_log = event
+            .paths
+            .iter()
+            .any(|path| path.extension().is_some_and(|ext| ext == "jsonl"));
+        if written && is_log {
+            let _ = tx.send(());
+        }
+    })
+}
@@ -1,7 +1,10 @@
 import { useState } from "react";
 import { Bell, X } from "lucide-react";
+import { invoke } from "@tauri-apps/api/core";
 import { openUrl } from "@tauri-apps/plugin-opener";
 import { useSettingsStore } from "../store/settingsStore";
+import { useClaudeCodeRoots } from "../hooks/useProviders";
+import BackfillPanel from "./BackfillPanel";
 
 interface AlertSettingsProps {
   onClose: () => void;
@@ -9,10 +12,15 @@ interface AlertSettingsProps {
 
 export default function AlertSettings({ onClose }: AlertSettingsProps) {
   const { settings, save } = useSettingsStore();
+  const claudeRoots = useClaudeCodeRoots();
   const [form, setForm] = useState({
     anthropic_key: settings.anthropic_key,
     openai_key: settings.openai_key,
     poll_interval_secs: settings.poll_interval_secs,
+    history_days: settings.history_days,
+    timezone: settings.timezone,
+    split_subagents: settings.split_subagents,
+    claude_config_dirs: settings.claude_config_dirs,
     alert_daily_usd: settings.alert_daily_usd,
     alert_monthly_usd: settings.alert_monthly_usd,
   });
@@ -21,7 +29,12 @@ export default function AlertSettings({ onClose }: AlertSettingsProps) {
 
   async function handleSave() {
     setSaving(true);
+    const timezoneChanged = form.timezone.trim() !== settings.timezone.trim();
     await save(form);
+    // Stored days were cut at the old zone's midnight; fetch them again
+    if (timezoneChanged) {
+      invoke("full_resync").catch(() => {});
+    }
     setSaving(false);
     setSaved(true);
     setTimeout(() => setSaved(false), 2000);
@@ -87,13 +100,16 @@ export default function AlertSettings({ onClose }: AlertSettingsProps) {
                 </p>
               </div>
               <div>
-                {field("OpenAI API Key", "openai_key", "sk-...", "password")}
+                {field("OpenAI Admin Key", "openai_key", "sk-admin-...", "password")}
                 <button
-                  onClick={() => openUrl("https://platform.openai.com/api-keys")}
+                  onClick={() => openUrl("https://platform.openai.com/settings/organization/admin-keys")}
                   className="text-xs text-indigo-400 hover:text-indigo-300 mt-1 transition-colors"
                 >
-                  Get API key (platform.openai.com) →
+                  Get Admin key (platform.openai.com) →
                 </button>
+                <p className="text-xs text-gray-600 mt-0.5">
+                  The organization Usage and Costs APIs require an Admin key.
+                </p>
               </div>
             </div>
           </div>
@@ -102,11 +118,80 @@ export default function AlertSettings({ onClose }: AlertSettingsProps) {
             <h3 className="text-xs font-semibold text-gray-500 uppercase tracking-wider mb-3">
               Polling
             </h3>
-            {field(
-              "Poll Interval (seconds)",
-              "poll_interval_secs",
-              "300",
-              "number",
+            <div className="space-y-3">
+              {field(
+                "Poll Interval (seconds)",
+                "poll_interval_secs",
+                "300",
+                "number",
+              )}
+              {field("History (days, up to 365)", "history_days", "30", "number")}
+              <div>
+                {field(
+                  "Timezone",
+                  "timezone",
+                  Intl.DateTimeFormat().resolvedOptions().timeZone,
+                )}
+                <p className="text-xs text-gray-600 mt-0.5">
+                  IANA name such as Europe/Berlin. Days start at midnight here;
+                  leave empty to use the system timezone.
+                </p>
+              </div>
+            </div>
+          </div>
+
+          <div>
+            <h3 className="text-xs font-semibold text-gray-500 uppercase tracking-wider mb-3">
+              Claude Code
+            </h3>
+            <label className="flex items-center gap-2 text-sm text-gray-400">
+              <input
+                type="checkbox"
+                checked={form.split_subagents === "true"}
+                onChange={(e) =>
+                  setForm({
+                    ...form,
+                    split_subagents: e.target.checked ? "true" : "false",
+                  })
+                }
+                className="accent-indigo-500"
+              />
+              Show subagent spend separately
+            </label>
+            <p className="text-xs text-gray-600 mt-0.5">
+              Subagent (Task) runs always count towards totals; this charts
+              them apart from the sessions that started them.
+            </p>
+            <label className="block text-sm text-gray-400 mt-3 mb-1">
+              More Config Directories
+            </label>
+            <textarea
+              value={form.claude_config_dirs}
+              onChange={(e) =>
+                setForm({ ...form, claude_config_dirs: e.target.value })
+              }
+              placeholder="work=~/.claude-work"
+              rows={2}
+              className="w-full bg-gray-800 border border-gray-700 rounded-lg px-3 py-2 text-sm text-gray-100 placeholder-gray-600 focus:outline-none focus:border-indigo-500 font-mono"
+            />
+            <p className="text-xs text-gray-600 mt-0.5">
+              One per line, optionally named as label=path. ~/.claude,
+              ~/.config/claude and CLAUDE_CONFIG_DIR are read without listing
+              them here.
+            </p>
+            {claudeRoots.length > 0 && (
+              <ul className="text-xs mt-1 space-y-0.5">
+                {claudeRoots.map((root) => (
+                  <li
+                    key={root.dir}
+                    title={root.dir}
+                    className={root.found ? "text-gray-400" : "text-gray-600"}
+                  >
+                    {root.label}
+                    {!root.found && " — not found"}
+                  </li>
+                ))}
+              </ul>
             )}
           </div>
 
@@ -124,6 +209,13 @@ export default function AlertSettings({ onClose }: AlertSettingsProps) {
               )}
             </div>
           </div>
+
+          <div>
+            <h3 className="text-xs font-semibold text-gray-500 uppercase tracking-wider mb-3">
+              Import History
+            </h3>
+            <BackfillPanel />
+          </div>
         </div>
 
         <div className="flex justify-end gap-3 mt-6">
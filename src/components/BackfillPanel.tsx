@@ -0,0 +1,118 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useProviders } from "../hooks/useProviders";
+import { addDays, localDate, useTimeZone } from "../hooks/useUsageData";
+
+interface BackfillJob {
+  provider: string;
+  start: string;
+  end: string;
+  next: string;
+}
+
+interface BackfillSummary {
+  provider: string;
+  start: string;
+  end: string;
+  snapshot_count: number;
+  missing_days: string[];
+}
+
+export default function BackfillPanel() {
+  const providers = useProviders();
+  const today = localDate(Date.now(), useTimeZone());
+  const [provider, setProvider] = useState("");
+  const [from, setFrom] = useState(addDays(today, -365));
+  const [to, setTo] = useState(today);
+  const [running, setRunning] = useState(false);
+  const [progress, setProgress] = useState<BackfillJob | null>(null);
+  const [result, setResult] = useState<string | null>(null);
+
+  const selected = provider || providers[0]?.id || "";
+
+  // Chunks report in as they are stored, including resumed jobs
+  useEffect(() => {
+    const unlisten = listen<BackfillJob>("backfill-progress", (event) => {
+      if (event.payload.provider === selected) setProgress(event.payload);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [selected]);
+
+  async function handleBackfill() {
+    setRunning(true);
+    setResult(null);
+    setProgress(null);
+    try {
+      const summary = await invoke<BackfillSummary>("backfill", {
+        provider: selected,
+        from,
+        to,
+      });
+      const missing = summary.missing_days.length;
+      setResult(
+        `Imported ${summary.snapshot_count} rows` +
+          (missing > 0 ? ` · ${missing} days unavailable` : ""),
+      );
+    } catch (e) {
+      // The job keeps its place; running it again resumes
+      setResult(`Backfill failed: ${e}`);
+    } finally {
+      setRunning(false);
+    }
+  }
+
+  const inputClass =
+    "bg-gray-800 border border-gray-700 rounded-lg px-3 py-2 text-sm text-gray-100 focus:outline-none focus:border-indigo-500";
+
+  return (
+    <div className="space-y-2">
+      <div className="flex gap-2">
+        <select
+          value={selected}
+          onChange={(e) => setProvider(e.target.value)}
+          className={`${inputClass} flex-1`}
+        >
+          {providers.map((p) => (
+            <option key={p.id} value={p.id}>
+              {p.display_name}
+            </option>
+          ))}
+        </select>
+        <input
+          type="date"
+          value={from}
+          onChange={(e) => setFrom(e.target.value)}
+          className={inputClass}
+        />
+        <input
+          type="date"
+          value={to}
+          onChange={(e) => setTo(e.target.value)}
+          className={inputClass}
+        />
+      </div>
+      <div className="flex items-center gap-3">
+        <button
+          onClick={handleBackfill}
+          disabled={running || !selected}
+          className="px-4 py-1.5 text-sm bg-gray-800 hover:bg-gray-700 disabled:opacity-50 rounded-lg transition-colors"
+        >
+          {running ? "Importing…" : "Import"}
+        </button>
+        {/* `next` is the first day still to fetch */}
+        <span className="text-xs text-gray-500">
+          {running && progress
+            ? `Stored through ${addDays(progress.next, -1)}`
+            : result}
+        </span>
+      </div>
+      <p className="text-xs text-gray-600">
+        Fetches up to a year back, a month at a time. An interrupted import
+        resumes when started again or on the next launch.
+      </p>
+    </div>
+  );
+}
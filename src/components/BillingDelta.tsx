@@ -0,0 +1,70 @@
+import type { CostComparison } from "../hooks/useUsageData";
+import type { ProviderInfo } from "../hooks/useProviders";
+
+interface BillingDeltaProps {
+  data: CostComparison[];
+  providers: ProviderInfo[];
+}
+
+function formatDelta(usd: number): string {
+  const sign = usd > 0 ? "+" : usd < 0 ? "−" : "";
+  return `${sign}$${Math.abs(usd).toFixed(2)}`;
+}
+
+export default function BillingDelta({ data, providers }: BillingDeltaProps) {
+  const names = Object.fromEntries(providers.map((p) => [p.id, p.display_name]));
+  const estimated = data.reduce((s, d) => s + d.estimated_usd, 0);
+  const billed = data.reduce((s, d) => s + d.billed_usd, 0);
+
+  // Newest first; the summary row covers the whole range
+  const rows = [...data].reverse();
+
+  return (
+    <div className="max-h-64 overflow-y-auto">
+      <table className="w-full text-sm">
+        <thead className="text-xs text-gray-500 text-left">
+          <tr>
+            <th
+              className="font-normal pb-2"
+              title="Providers bill by UTC day; each row compares the bill with the estimate over the same UTC day"
+            >
+              Date
+            </th>
+            <th className="font-normal pb-2">Provider</th>
+            <th className="font-normal pb-2 text-right">Estimated</th>
+            <th className="font-normal pb-2 text-right">Billed</th>
+            <th className="font-normal pb-2 text-right">Delta</th>
+          </tr>
+        </thead>
+        <tbody className="text-gray-300">
+          <tr className="border-b border-gray-800 font-semibold">
+            <td className="py-1.5">Total</td>
+            <td />
+            <td className="text-right">${estimated.toFixed(2)}</td>
+            <td className="text-right">${billed.toFixed(2)}</td>
+            <td className="text-right">{formatDelta(billed - estimated)}</td>
+          </tr>
+          {rows.map((d) => (
+            <tr key={`${d.date}:${d.provider}`}>
+              <td className="py-1 text-gray-400">{d.date}</td>
+              <td className="text-gray-400">{names[d.provider] ?? d.provider}</td>
+              <td className="text-right">${d.estimated_usd.toFixed(2)}</td>
+              <td className="text-right">${d.billed_usd.toFixed(2)}</td>
+              <td
+                className={`text-right ${
+                  Math.abs(d.delta_usd) < 0.01
+                    ? "text-gray-500"
+                    : d.delta_usd > 0
+                      ? "text-red-400"
+                      : "text-green-400"
+                }`}
+              >
+                {formatDelta(d.delta_usd)}
+              </td>
+            </tr>
+          ))}
+        </tbody>
+      </table>
+    </div>
+  );
+}
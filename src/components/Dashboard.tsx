@@ -1,18 +1,42 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { RefreshCw, Settings } from "lucide-react";
+import { RefreshCw, RotateCcw, Settings } from "lucide-react";
 import ProviderCard from "./ProviderCard";
 import UsageChart from "./UsageChart";
 import ModelBreakdown from "./ModelBreakdown";
+import BillingDelta from "./BillingDelta";
+import ProjectBreakdown from "./ProjectBreakdown";
+import HourlyTimeline from "./HourlyTimeline";
 import AlertSettings from "./AlertSettings";
-import { useUsageData } from "../hooks/useUsageData";
+import {
+  useUsageData,
+  useHourlyTimeline,
+  useClaudeCodeBreakdown,
+  useTimeZone,
+  useSplitSubagents,
+  localDate,
+} from "../hooks/useUsageData";
 import { useAlerts } from "../hooks/useAlerts";
+import {
+  useProviders,
+  useProviderStatus,
+  providerColor,
+  SUBAGENTS,
+} from "../hooks/useProviders";
 import { useSettingsStore } from "../store/settingsStore";
 
+interface PollSummary {
+  snapshot_count: number;
+  today_cost_usd: number;
+  finished_at: string;
+}
+
 export default function Dashboard() {
   const [showSettings, setShowSettings] = useState(false);
   const [refreshing, setRefreshing] = useState(false);
+  const [refreshResult, setRefreshResult] = useState<string | null>(null);
   const { settings, loaded } = useSettingsStore();
+  const historyDays = Math.min(Number(settings.history_days) || 30, 365);
 
   // Auto-open settings on first launch if neither key is configured
   useEffect(() => {
@@ -24,26 +48,42 @@ export default function Dashboard() {
   const {
     dailyTotals,
     modelTotals,
-    todayAnthropicCost,
-    todayOpenaiCost,
-    todayClaudeCodeCost,
-    todayAnthropicTokens,
-    todayOpenaiTokens,
-    todayClaudeCodeTokens,
+    costComparison,
+    todayByProvider,
     isLoading,
     refetch,
-  } = useUsageData(30);
+  } = useUsageData(historyDays);
+  const providers = useProviders();
+  // Subagent spend gets its own card and series only when split out
+  const split = useSplitSubagents();
+  const shownProviders = split ? [...providers, SUBAGENTS] : providers;
+  const providerStatus = useProviderStatus();
+  const timeZone = useTimeZone();
+  const [hourlyDate, setHourlyDate] = useState(() =>
+    localDate(Date.now(), timeZone),
+  );
+  const { hourly, isLoading: hourlyLoading } = useHourlyTimeline(hourlyDate);
+  const { projects, sessions, profiles } = useClaudeCodeBreakdown(historyDays);
 
   // Alert monitoring runs silently in the background
   useAlerts();
 
-  async function handleRefresh() {
+  // A full resync drops the fetch watermarks first, so the whole history
+  // window is fetched again instead of just the last few days
+  async function handleRefresh(fullResync = false) {
     setRefreshing(true);
+    setRefreshResult(null);
     try {
-      await invoke("trigger_refresh");
-      // Give the poller ~1s to emit events, then refetch from DB
-      await new Promise((r) => setTimeout(r, 1000));
+      // Resolves once the poller has finished the forced poll
+      const summary = await invoke<PollSummary>(
+        fullResync ? "full_resync" : "trigger_refresh",
+      );
       refetch();
+      setRefreshResult(
+        `Updated ${new Date(summary.finished_at).toLocaleTimeString()} · ${summary.snapshot_count} rows`,
+      );
+    } catch (e) {
+      setRefreshResult(`Refresh failed: ${e}`);
     } finally {
       setRefreshing(false);
     }
@@ -58,13 +98,25 @@ export default function Dashboard() {
         <div>
           <h1 className="text-xl font-bold tracking-tight">Token Tracker</h1>
           <p className="text-xs text-gray-500 mt-0.5">
-            30-day total:{" "}
+            {historyDays}-day total:{" "}
             <span className="text-gray-300">${monthlyTotal.toFixed(2)}</span>
           </p>
         </div>
         <div className="flex items-center gap-2">
+          {refreshResult && (
+            <span className="text-xs text-gray-500">{refreshResult}</span>
+          )}
+          <button
+            onClick={() => handleRefresh(true)}
+            disabled={refreshing}
+            title={`Re-fetch the last ${historyDays} days from every provider`}
+            className="flex items-center gap-1.5 px-3 py-1.5 text-sm text-gray-400 hover:text-gray-200 hover:bg-gray-800 rounded-lg transition-colors disabled:opacity-50"
+          >
+            <RotateCcw size={14} />
+            Resync
+          </button>
           <button
-            onClick={handleRefresh}
+            onClick={() => handleRefresh()}
             disabled={refreshing}
             title="Refresh now"
             className="flex items-center gap-1.5 px-3 py-1.5 text-sm text-gray-400 hover:text-gray-200 hover:bg-gray-800 rounded-lg transition-colors disabled:opacity-50"
@@ -90,40 +142,58 @@ export default function Dashboard() {
       <main className="flex-1 overflow-y-auto px-6 py-5 space-y-6">
         {/* Provider cards */}
         <div className="flex gap-4">
-          <ProviderCard
-            name="Anthropic"
-            color="#f59e0b"
-            todayCost={todayAnthropicCost}
-            todayTokens={todayAnthropicTokens}
-            hasKey={!!settings.anthropic_key}
-          />
-          <ProviderCard
-            name="OpenAI"
-            color="#6366f1"
-            todayCost={todayOpenaiCost}
-            todayTokens={todayOpenaiTokens}
-            hasKey={!!settings.openai_key}
-          />
-          <ProviderCard
-            name="Claude Code"
-            color="#10b981"
-            todayCost={todayClaudeCodeCost}
-            todayTokens={todayClaudeCodeTokens}
-            hasKey={true}
-          />
+          {shownProviders.map((p, i) => (
+            <ProviderCard
+              key={p.id}
+              name={p.display_name}
+              color={providerColor(p.id, i)}
+              todayCost={todayByProvider[p.id]?.cost ?? 0}
+              todayTokens={todayByProvider[p.id]?.tokens ?? 0}
+              hasKey={p.required_settings.every(
+                (k) => !!(settings as unknown as Record<string, string>)[k],
+              )}
+              status={providerStatus[p.id]}
+            />
+          ))}
         </div>
 
         {/* Daily usage chart */}
         <section className="bg-gray-900 rounded-xl p-5 border border-gray-800">
           <h2 className="text-sm font-semibold text-gray-400 mb-4">
-            Daily Cost — Last 30 Days
+            Daily Cost — Last {historyDays} Days
           </h2>
           {isLoading ? (
             <div className="h-[220px] flex items-center justify-center text-gray-500 text-sm">
               Loading…
             </div>
           ) : (
-            <UsageChart data={dailyTotals} />
+            <UsageChart data={dailyTotals} providers={shownProviders} />
+          )}
+        </section>
+
+        {/* Hour-by-hour cost for one day */}
+        <section className="bg-gray-900 rounded-xl p-5 border border-gray-800">
+          <div className="flex items-center justify-between mb-4">
+            <h2 className="text-sm font-semibold text-gray-400">
+              Hourly Cost
+            </h2>
+            <input
+              type="date"
+              value={hourlyDate}
+              onChange={(e) => e.target.value && setHourlyDate(e.target.value)}
+              className="bg-gray-800 border border-gray-700 rounded-lg px-2 py-1 text-xs text-gray-300 focus:outline-none focus:border-indigo-500"
+            />
+          </div>
+          {hourlyLoading ? (
+            <div className="h-[200px] flex items-center justify-center text-gray-500 text-sm">
+              Loading…
+            </div>
+          ) : (
+            <HourlyTimeline
+              data={hourly}
+              providers={shownProviders}
+              timeZone={timeZone}
+            />
           )}
         </section>
 
@@ -141,6 +211,30 @@ export default function Dashboard() {
           )}
         </section>
 
+        {/* Where Claude Code spend went, by repo and session */}
+        {projects.length > 0 && (
+          <section className="bg-gray-900 rounded-xl p-5 border border-gray-800">
+            <h2 className="text-sm font-semibold text-gray-400 mb-4">
+              Claude Code by Project
+            </h2>
+            <ProjectBreakdown
+              projects={projects}
+              sessions={sessions}
+              profiles={profiles}
+            />
+          </section>
+        )}
+
+        {/* Estimate vs. billed, for providers that report billed cost */}
+        {costComparison.length > 0 && (
+          <section className="bg-gray-900 rounded-xl p-5 border border-gray-800">
+            <h2 className="text-sm font-semibold text-gray-400 mb-4">
+              Estimated vs. Billed
+            </h2>
+            <BillingDelta data={costComparison} providers={providers} />
+          </section>
+        )}
+
         {/* Alert notice */}
         {settings.alert_daily_usd && (
           <p className="text-xs text-gray-600 text-center pb-2">
@@ -0,0 +1,80 @@
+import {
+  BarChart,
+  Bar,
+  XAxis,
+  YAxis,
+  CartesianGrid,
+  Tooltip,
+  ResponsiveContainer,
+} from "recharts";
+import type { HourlyTotal } from "../hooks/useUsageData";
+import { providerColor, type ProviderInfo } from "../hooks/useProviders";
+
+interface HourlyTimelineProps {
+  data: HourlyTotal[];
+  providers: ProviderInfo[];
+  // Zone the day was cut in; undefined is the system zone
+  timeZone?: string;
+}
+
+export default function HourlyTimeline({
+  data,
+  providers,
+  timeZone,
+}: HourlyTimelineProps) {
+  // Hours arrive as UTC instants; label them with the local clock time
+  const hourFormat = new Intl.DateTimeFormat("en-GB", {
+    timeZone,
+    hour: "2-digit",
+    minute: "2-digit",
+  });
+  const formatHour = (hour: string) => hourFormat.format(Date.parse(hour));
+
+  if (data.every((h) => h.total === 0)) {
+    return (
+      <div className="h-48 flex items-center justify-center text-gray-500 text-sm">
+        No usage recorded on this day.
+      </div>
+    );
+  }
+
+  return (
+    <ResponsiveContainer width="100%" height={200}>
+      <BarChart data={data} margin={{ top: 4, right: 16, left: 0, bottom: 0 }}>
+        <CartesianGrid strokeDasharray="3 3" stroke="#1f2937" vertical={false} />
+        <XAxis
+          dataKey="hour"
+          tickFormatter={formatHour}
+          tick={{ fill: "#9ca3af", fontSize: 11 }}
+          axisLine={{ stroke: "#374151" }}
+          tickLine={false}
+          interval={2}
+        />
+        <YAxis
+          tick={{ fill: "#9ca3af", fontSize: 11 }}
+          axisLine={false}
+          tickLine={false}
+          tickFormatter={(v) => `$${v.toFixed(2)}`}
+          width={52}
+        />
+        <Tooltip
+          cursor={{ fill: "#1f2937" }}
+          contentStyle={{
+            backgroundColor: "#111827",
+            border: "1px solid #374151",
+            borderRadius: 8,
+            color: "#f9fafb",
+          }}
+          formatter={(value: number | undefined, name) => [
+            `$${(value ?? 0).toFixed(4)}`,
+            providers.find((p) => p.id === name)?.display_name ?? name,
+          ]}
+          labelFormatter={(label) => formatHour(String(label))}
+        />
+        {providers.map((p, i) => (
+          <Bar key={p.id} dataKey={p.id} stackId="cost" fill={providerColor(p.id, i)} />
+        ))}
+      </BarChart>
+    </ResponsiveContainer>
+  );
+}
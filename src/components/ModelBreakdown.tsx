@@ -9,16 +9,12 @@ import {
   Cell,
 } from "recharts";
 import type { ModelTotal } from "../hooks/useUsageData";
+import { providerColor } from "../hooks/useProviders";
 
 interface ModelBreakdownProps {
   data: ModelTotal[];
 }
 
-const COLORS: Record<string, string> = {
-  anthropic: "#f59e0b",
-  openai: "#6366f1",
-};
-
 function shortModelName(model: string): string {
   // e.g. "claude-sonnet-4-6-20251001" → "Sonnet 4.6"
   const m = model
@@ -82,7 +78,7 @@ export default function ModelBreakdown({ data }: ModelBreakdownProps) {
           {chartData.map((entry, i) => (
             <Cell
               key={i}
-              fill={COLORS[entry.provider] ?? "#6b7280"}
+              fill={providerColor(entry.provider)}
             />
           ))}
         </Bar>
@@ -0,0 +1,106 @@
+import type {
+  ProfileTotal,
+  ProjectTotal,
+  SessionTotal,
+} from "../hooks/useUsageData";
+
+interface ProjectBreakdownProps {
+  projects: ProjectTotal[];
+  sessions: SessionTotal[];
+  profiles: ProfileTotal[];
+}
+
+function formatTokens(n: number): string {
+  if (n >= 1_000_000) return `${(n / 1_000_000).toFixed(1)}M`;
+  if (n >= 1_000) return `${(n / 1_000).toFixed(1)}K`;
+  return n.toString();
+}
+
+// Last path segment, which is usually enough to recognise a repo
+function projectName(project: string): string {
+  return project.split(/[\\/]/).filter(Boolean).pop() ?? project;
+}
+
+export default function ProjectBreakdown({
+  projects,
+  sessions,
+  profiles,
+}: ProjectBreakdownProps) {
+  // Profiles only need naming once there is more than one
+  const multiProfile = profiles.length > 1;
+  return (
+    <div className="space-y-4">
+      {multiProfile && (
+        <div className="flex flex-wrap gap-2">
+          {profiles.map((p) => (
+            <div
+              key={p.profile}
+              className="bg-gray-800 rounded-lg px-3 py-1.5 text-sm"
+              title={`${p.sessions} session${p.sessions === 1 ? "" : "s"} · ${formatTokens(p.tokens)} tokens`}
+            >
+              <span className="text-gray-400">{p.profile}</span>
+              <span className="text-gray-200 ml-2">
+                ${p.cost_usd.toFixed(2)}
+              </span>
+            </div>
+          ))}
+        </div>
+      )}
+      <div className="grid grid-cols-2 gap-6">
+        <table className="w-full text-sm">
+          <thead className="text-xs text-gray-500 text-left">
+            <tr>
+              <th className="font-normal pb-2">Project</th>
+              <th className="font-normal pb-2 text-right">Sessions</th>
+              <th className="font-normal pb-2 text-right">Tokens</th>
+              <th className="font-normal pb-2 text-right">Cost</th>
+            </tr>
+          </thead>
+          <tbody className="text-gray-300">
+            {projects.map((p) => (
+              <tr key={`${p.profile}\n${p.project}`}>
+                <td className="py-1 truncate max-w-[12rem]" title={p.project}>
+                  {projectName(p.project)}
+                  {multiProfile && (
+                    <span className="text-gray-600 ml-1">{p.profile}</span>
+                  )}
+                </td>
+                <td className="text-right text-gray-400">{p.sessions}</td>
+                <td className="text-right text-gray-400">
+                  {formatTokens(p.tokens)}
+                </td>
+                <td className="text-right">${p.cost_usd.toFixed(2)}</td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+        <table className="w-full text-sm">
+          <thead className="text-xs text-gray-500 text-left">
+            <tr>
+              <th className="font-normal pb-2">Session</th>
+              <th className="font-normal pb-2">Last used</th>
+              <th className="font-normal pb-2 text-right">Cost</th>
+            </tr>
+          </thead>
+          <tbody className="text-gray-300">
+            {sessions.map((s) => (
+              <tr key={s.session_id}>
+                <td
+                  className="py-1 truncate max-w-[12rem]"
+                  title={`${s.project}\n${s.session_id}\n${s.profile}`}
+                >
+                  {projectName(s.project)}
+                  <span className="text-gray-600 ml-1">
+                    {s.session_id.slice(0, 8)}
+                  </span>
+                </td>
+                <td className="text-gray-400">{s.last_date}</td>
+                <td className="text-right">${s.cost_usd.toFixed(2)}</td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+      </div>
+    </div>
+  );
+}
@@ -1,11 +1,22 @@
+import type { ProviderStatus } from "../hooks/useProviders";
+
 interface ProviderCardProps {
   name: string;
   color: string;
   todayCost: number;
   todayTokens: number;
   hasKey: boolean;
+  status?: ProviderStatus;
 }
 
+const ERROR_LABELS: Record<string, string> = {
+  auth: "Key rejected",
+  rate_limited: "Rate limited",
+  network: "Offline",
+  parse: "Bad response",
+  upstream: "Provider down",
+};
+
 function formatTokens(n: number): string {
   if (n >= 1_000_000) return `${(n / 1_000_000).toFixed(1)}M`;
   if (n >= 1_000) return `${(n / 1_000).toFixed(1)}K`;
@@ -18,7 +29,12 @@ export default function ProviderCard({
   todayCost,
   todayTokens,
   hasKey,
+  status,
 }: ProviderCardProps) {
+  const failing = hasKey && !!status && status.consecutive_failures > 0;
+  const missingDays = status?.missing_days ?? [];
+  const duplicates = status?.duplicates_dropped ?? 0;
+  const warnings = status?.warnings ?? [];
   return (
     <div className="bg-gray-900 rounded-xl p-5 border border-gray-800 flex-1 min-w-[200px]">
       <div className="flex items-center gap-2 mb-3">
@@ -34,6 +50,14 @@ export default function ProviderCard({
             No key
           </span>
         )}
+        {failing && (
+          <span
+            title={status?.last_error ?? undefined}
+            className="ml-auto text-xs text-red-400 bg-red-500/10 px-2 py-0.5 rounded-full"
+          >
+            {ERROR_LABELS[status?.last_error_kind ?? ""] ?? "Fetch failed"}
+          </span>
+        )}
       </div>
       <p className="text-3xl font-bold text-white">
         ${todayCost.toFixed(2)}
@@ -42,6 +66,27 @@ export default function ProviderCard({
       <p className="text-sm text-gray-400 mt-1">
         {formatTokens(todayTokens)} tokens
       </p>
+      {missingDays.length > 0 && (
+        <p
+          className="text-xs text-yellow-500 mt-1"
+          title={`Could not fetch: ${missingDays.join(", ")}`}
+        >
+          {missingDays.length} day{missingDays.length === 1 ? "" : "s"} missing
+        </p>
+      )}
+      {duplicates > 0 && (
+        <p
+          className="text-xs text-gray-500 mt-1"
+          title="Responses logged more than once (resumed or forked sessions, streamed lines) are counted once"
+        >
+          {duplicates} duplicate{duplicates === 1 ? "" : "s"} skipped
+        </p>
+      )}
+      {warnings.length > 0 && (
+        <p className="text-xs text-yellow-500 mt-1" title={warnings.join("\n")}>
+          {warnings.length} warning{warnings.length === 1 ? "" : "s"}
+        </p>
+      )}
     </div>
   );
 }
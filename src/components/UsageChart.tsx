@@ -9,21 +9,19 @@ import {
   ResponsiveContainer,
 } from "recharts";
 import type { DailyTotal } from "../hooks/useUsageData";
+import { providerColor, type ProviderInfo } from "../hooks/useProviders";
 
 interface UsageChartProps {
   data: DailyTotal[];
+  providers: ProviderInfo[];
 }
 
-const ANTHROPIC_COLOR = "#f59e0b";
-const OPENAI_COLOR = "#6366f1";
-const CLAUDE_CODE_COLOR = "#10b981";
-
 function formatDate(dateStr: string) {
   const d = new Date(dateStr + "T00:00:00");
   return d.toLocaleDateString("en-US", { month: "short", day: "numeric" });
 }
 
-export default function UsageChart({ data }: UsageChartProps) {
+export default function UsageChart({ data, providers }: UsageChartProps) {
   if (data.length === 0) {
     return (
       <div className="h-48 flex items-center justify-center text-gray-500 text-sm">
@@ -64,37 +62,20 @@ export default function UsageChart({ data }: UsageChartProps) {
         <Legend
           wrapperStyle={{ paddingTop: 8, fontSize: 12 }}
           formatter={(value) =>
-            value === "anthropic"
-              ? "Anthropic"
-              : value === "openai"
-              ? "OpenAI"
-              : "Claude Code"
+            providers.find((p) => p.id === value)?.display_name ?? value
           }
         />
-        <Line
-          type="monotone"
-          dataKey="anthropic"
-          stroke={ANTHROPIC_COLOR}
-          strokeWidth={2}
-          dot={false}
-          activeDot={{ r: 4 }}
-        />
-        <Line
-          type="monotone"
-          dataKey="openai"
-          stroke={OPENAI_COLOR}
-          strokeWidth={2}
-          dot={false}
-          activeDot={{ r: 4 }}
-        />
-        <Line
-          type="monotone"
-          dataKey="claude_code"
-          stroke={CLAUDE_CODE_COLOR}
-          strokeWidth={2}
-          dot={false}
-          activeDot={{ r: 4 }}
-        />
+        {providers.map((p, i) => (
+          <Line
+            key={p.id}
+            type="monotone"
+            dataKey={p.id}
+            stroke={providerColor(p.id, i)}
+            strokeWidth={2}
+            dot={false}
+            activeDot={{ r: 4 }}
+          />
+        ))}
       </LineChart>
     </ResponsiveContainer>
   );
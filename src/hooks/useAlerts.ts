@@ -5,9 +5,7 @@ import { useUsageData } from "./useUsageData";
 
 export function useAlerts() {
   const { settings } = useSettingsStore();
-  const { todayAnthropicCost, todayOpenaiCost } = useUsageData();
-
-  const todayTotal = todayAnthropicCost + todayOpenaiCost;
+  const { todayTotal } = useUsageData();
 
   useEffect(() => {
     async function checkAlerts() {
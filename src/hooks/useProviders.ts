@@ -0,0 +1,108 @@
+import { useEffect } from "react";
+import { useQuery, useQueryClient } from "@tanstack/react-query";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useSettingsStore } from "../store/settingsStore";
+
+export interface ProviderInfo {
+  id: string;
+  display_name: string;
+  required_settings: string[];
+}
+
+export interface ProviderStatus {
+  id: string;
+  last_attempt: string | null;
+  last_success: string | null;
+  last_error: string | null;
+  last_error_kind:
+    | "missing_setting"
+    | "auth"
+    | "rate_limited"
+    | "network"
+    | "parse"
+    | "upstream"
+    | "other"
+    | null;
+  consecutive_failures: number;
+  missing_days: string[];
+  // Records the last fetch skipped as repeats of ones already counted
+  duplicates_dropped: number;
+  // Parts of the last fetch that failed without failing it
+  warnings: string[];
+}
+
+const PROVIDER_COLORS: Record<string, string> = {
+  anthropic: "#f59e0b",
+  openai: "#6366f1",
+  claude_code: "#10b981",
+  claude_code_subagents: "#6ee7b7",
+};
+
+const FALLBACK_COLORS = ["#ec4899", "#06b6d4", "#84cc16", "#f97316", "#a855f7"];
+
+// Claude Code usage from subagent (Task) runs, stored under its own id. It is
+// only listed when the user chooses to see it apart from Claude Code.
+export const SUBAGENTS: ProviderInfo = {
+  id: "claude_code_subagents",
+  display_name: "Claude Code Subagents",
+  required_settings: [],
+};
+
+export function providerColor(id: string, index = 0): string {
+  return PROVIDER_COLORS[id] ?? FALLBACK_COLORS[index % FALLBACK_COLORS.length];
+}
+
+// A Claude Code config directory whose logs are read
+export interface ClaudeCodeRoot {
+  label: string;
+  dir: string;
+  // Whether it holds a projects directory yet
+  found: boolean;
+}
+
+// Re-read whenever the configured directories are saved
+export function useClaudeCodeRoots() {
+  const configDirs = useSettingsStore((s) => s.settings.claude_config_dirs);
+  const query = useQuery({
+    queryKey: ["claude-code-roots", configDirs],
+    queryFn: () => invoke<ClaudeCodeRoot[]>("get_claude_code_roots"),
+  });
+  return query.data ?? [];
+}
+
+export function useProviders() {
+  const query = useQuery({
+    queryKey: ["providers"],
+    queryFn: () => invoke<ProviderInfo[]>("list_providers"),
+    staleTime: Infinity,
+  });
+  return query.data ?? [];
+}
+
+// Fetch health per provider id, kept current by the poller's provider-status events
+export function useProviderStatus(): Record<string, ProviderStatus> {
+  const queryClient = useQueryClient();
+
+  useEffect(() => {
+    const unlisten = listen<ProviderStatus>("provider-status", (event) => {
+      queryClient.setQueryData<ProviderStatus[]>(["provider-status"], (prev) =>
+        (prev ?? []).some((s) => s.id === event.payload.id)
+          ? prev!.map((s) => (s.id === event.payload.id ? event.payload : s))
+          : [...(prev ?? []), event.payload],
+      );
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [queryClient]);
+
+  const query = useQuery({
+    queryKey: ["provider-status"],
+    queryFn: () => invoke<ProviderStatus[]>("get_provider_status"),
+  });
+
+  const byId: Record<string, ProviderStatus> = {};
+  for (const s of query.data ?? []) byId[s.id] = s;
+  return byId;
+}
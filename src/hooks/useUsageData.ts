@@ -1,153 +1,218 @@
 import { useEffect, useCallback } from "react";
 import { useQuery, useQueryClient } from "@tanstack/react-query";
+import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import Database from "@tauri-apps/plugin-sql";
+import { useSettingsStore } from "../store/settingsStore";
+import { SUBAGENTS } from "./useProviders";
+
+export type UsageKind =
+  | "text"
+  | "batch"
+  | "embeddings"
+  | "moderation"
+  | "images"
+  | "audio_speech"
+  | "audio_transcription"
+  | "vector_store";
+
+export type Unit = "tokens" | "images" | "characters" | "seconds" | "gb_days";
 
 export interface UsageSnapshot {
   id?: number;
-  provider: "anthropic" | "openai" | "claude_code";
+  provider: string;
   model: string;
   date: string;
   input_tokens: number;
   output_tokens: number;
   cache_tokens: number;
+  cache_creation_5m_tokens: number;
+  cache_creation_1h_tokens: number;
   cost_usd: number;
+  estimated_cost_usd: number;
+  billed_cost_usd: number | null;
   fetched_at: string;
+  usage_kind: UsageKind;
+  unit: Unit;
+  // Amount in `unit` for usage not measured in tokens
+  quantity: number;
+  unpriced?: boolean;
 }
 
+// Per-group cost for one day, keyed by provider id or model, plus the
+// day's total cost and tokens.
 export interface DailyTotal {
   date: string;
-  anthropic: number;
-  openai: number;
-  claude_code: number;
   total: number;
+  tokens: number;
+  [group: string]: number | string;
+}
+
+// One hour of a local day; `hour` is its UTC start, YYYY-MM-DDTHH:00:00Z
+export interface HourlyTotal {
+  hour: string;
+  total: number;
+  tokens: number;
+  [group: string]: number | string;
+}
+
+export interface ProviderToday {
+  cost: number;
+  tokens: number;
 }
 
 export interface ModelTotal {
   provider: string;
   model: string;
+  input_tokens: number;
+  output_tokens: number;
   total_tokens: number;
   cost_usd: number;
 }
 
-let db: Awaited<ReturnType<typeof Database.load>> | null = null;
-
-async function getDb() {
-  if (!db) {
-    db = await Database.load("sqlite:token_tracker.db");
-    // Initialise schema
-    await db.execute(`
-      CREATE TABLE IF NOT EXISTS usage_snapshots (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        provider TEXT NOT NULL,
-        model TEXT NOT NULL,
-        date TEXT NOT NULL,
-        input_tokens INTEGER DEFAULT 0,
-        output_tokens INTEGER DEFAULT 0,
-        cache_tokens INTEGER DEFAULT 0,
-        cost_usd REAL DEFAULT 0,
-        fetched_at TEXT NOT NULL,
-        UNIQUE(provider, model, date)
-      );
-      CREATE TABLE IF NOT EXISTS settings (
-        key TEXT PRIMARY KEY,
-        value TEXT NOT NULL
-      );
-    `);
+export interface ProviderTotal {
+  provider: string;
+  cost_usd: number;
+  tokens: number;
+}
+
+// Estimate vs. billed for one provider-day; only providers with billing data appear
+export interface CostComparison {
+  date: string;
+  provider: string;
+  estimated_usd: number;
+  billed_usd: number;
+  delta_usd: number;
+}
+
+// Claude Code spend for one config directory (profile)
+export interface ProfileTotal {
+  profile: string;
+  cost_usd: number;
+  tokens: number;
+  sessions: number;
+}
+
+// Claude Code spend for one project directory
+export interface ProjectTotal {
+  project: string;
+  profile: string;
+  cost_usd: number;
+  tokens: number;
+  sessions: number;
+  last_date: string;
+}
+
+// Claude Code spend for one session, subagent runs included
+export interface SessionTotal {
+  session_id: string;
+  project: string;
+  profile: string;
+  cost_usd: number;
+  tokens: number;
+  first_date: string;
+  last_date: string;
+}
+
+export type Period = "today" | "week" | "month";
+
+export interface PeriodSummary {
+  period: Period;
+  start: string;
+  end: string;
+  cost_usd: number;
+  tokens: number;
+  by_provider: ProviderTotal[];
+}
+
+interface DateRange {
+  start: string;
+  end: string;
+}
+
+// The configured timezone, or undefined for the system zone. A name Intl
+// does not know also falls back to the system zone, as it does in Rust.
+export function useTimeZone(): string | undefined {
+  const name = useSettingsStore((s) => s.settings.timezone).trim();
+  if (!name) return undefined;
+  try {
+    new Intl.DateTimeFormat("en-CA", { timeZone: name });
+    return name;
+  } catch {
+    return undefined;
   }
-  return db;
-}
-
-async function fetchSnapshots(days = 30): Promise<UsageSnapshot[]> {
-  const d = await getDb();
-  const cutoff = new Date();
-  cutoff.setDate(cutoff.getDate() - days);
-  const cutoffStr = cutoff.toISOString().slice(0, 10);
-  return d.select<UsageSnapshot[]>(
-    "SELECT * FROM usage_snapshots WHERE date >= ? ORDER BY date ASC",
-    [cutoffStr],
-  );
-}
-
-export function upsertSnapshots(snapshots: UsageSnapshot[]) {
-  return getDb().then(async (d) => {
-    for (const s of snapshots) {
-      await d.execute(
-        `INSERT INTO usage_snapshots
-           (provider, model, date, input_tokens, output_tokens, cache_tokens, cost_usd, fetched_at)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-         ON CONFLICT(provider, model, date) DO UPDATE SET
-           input_tokens = excluded.input_tokens,
-           output_tokens = excluded.output_tokens,
-           cache_tokens = excluded.cache_tokens,
-           cost_usd = excluded.cost_usd,
-           fetched_at = excluded.fetched_at`,
-        [
-          s.provider,
-          s.model,
-          s.date,
-          s.input_tokens,
-          s.output_tokens,
-          s.cache_tokens,
-          s.cost_usd,
-          s.fetched_at,
-        ],
-      );
-    }
+}
+
+// YYYY-MM-DD of `ms` in `timeZone` (en-CA formats dates that way)
+export function localDate(ms: number, timeZone?: string): string {
+  return new Intl.DateTimeFormat("en-CA", {
+    timeZone,
+    year: "numeric",
+    month: "2-digit",
+    day: "2-digit",
+  }).format(ms);
+}
+
+// Calendar arithmetic on YYYY-MM-DD strings, done in UTC so DST never
+// shifts the result
+export function addDays(day: string, days: number): string {
+  return new Date(Date.parse(day) + days * 86_400_000).toISOString().slice(0, 10);
+}
+
+// Whether subagent spend is shown as its own provider
+export function useSplitSubagents(): boolean {
+  return useSettingsStore((s) => s.settings.split_subagents) === "true";
+}
+
+// Unless shown separately, subagent usage counts as Claude Code's own
+function foldSubagents<T extends DailyTotal | HourlyTotal>(rows: T[]): T[] {
+  return rows.map((row) => {
+    if (!(SUBAGENTS.id in row)) return row;
+    const { [SUBAGENTS.id]: subagents, ...rest } = row;
+    return {
+      ...rest,
+      claude_code: Number(rest.claude_code ?? 0) + Number(subagents),
+    } as T;
   });
 }
 
-function aggregateDailyTotals(snapshots: UsageSnapshot[]): DailyTotal[] {
-  const map = new Map<string, DailyTotal>();
-  for (const s of snapshots) {
-    if (!map.has(s.date)) {
-      map.set(s.date, { date: s.date, anthropic: 0, openai: 0, claude_code: 0, total: 0 });
-    }
-    const day = map.get(s.date)!;
-    day[s.provider] += s.cost_usd;
-    day.total += s.cost_usd;
+function foldSubagentModels(models: ModelTotal[]): ModelTotal[] {
+  const merged = new Map<string, ModelTotal>();
+  for (const m of models) {
+    const provider = m.provider === SUBAGENTS.id ? "claude_code" : m.provider;
+    const key = `${provider}/${m.model}`;
+    const prev = merged.get(key);
+    merged.set(
+      key,
+      prev
+        ? {
+            ...prev,
+            input_tokens: prev.input_tokens + m.input_tokens,
+            output_tokens: prev.output_tokens + m.output_tokens,
+            total_tokens: prev.total_tokens + m.total_tokens,
+            cost_usd: prev.cost_usd + m.cost_usd,
+          }
+        : { ...m, provider },
+    );
   }
-  return Array.from(map.values()).sort((a, b) => a.date.localeCompare(b.date));
-}
-
-function aggregateModelTotals(snapshots: UsageSnapshot[]): ModelTotal[] {
-  const map = new Map<string, ModelTotal>();
-  for (const s of snapshots) {
-    const key = `${s.provider}:${s.model}`;
-    if (!map.has(key)) {
-      map.set(key, {
-        provider: s.provider,
-        model: s.model,
-        total_tokens: 0,
-        cost_usd: 0,
-      });
-    }
-    const m = map.get(key)!;
-    m.total_tokens += s.input_tokens + s.output_tokens;
-    m.cost_usd += s.cost_usd;
-  }
-  return Array.from(map.values()).sort((a, b) => b.cost_usd - a.cost_usd);
+  return [...merged.values()].sort((a, b) => b.cost_usd - a.cost_usd);
+}
+
+// Same window the poller fetches for history_days, in local dates
+function lastDays(days: number, timeZone?: string): DateRange {
+  const end = localDate(Date.now(), timeZone);
+  return { start: addDays(end, -days), end };
 }
 
 export function useUsageData(days = 30) {
   const queryClient = useQueryClient();
+  const timeZone = useTimeZone();
+  const split = useSplitSubagents();
 
   const refetch = useCallback(() => {
-    queryClient.invalidateQueries({ queryKey: ["usage", days] });
-  }, [queryClient, days]);
-
-  // Listen for new snapshots from Rust poller and upsert them
-  useEffect(() => {
-    const unlisten = listen<UsageSnapshot[]>("new-snapshots", async (event) => {
-      await upsertSnapshots(event.payload);
-      refetch();
-    });
-    return () => {
-      unlisten.then((fn) => fn());
-    };
-  }, [refetch]);
+    queryClient.invalidateQueries({ queryKey: ["usage"] });
+  }, [queryClient]);
 
-  // Listen for usage-updated event (refetch signal)
+  // The Rust poller stores new snapshots itself and then emits usage-updated
   useEffect(() => {
     const unlisten = listen("usage-updated", () => refetch());
     return () => {
@@ -155,49 +220,105 @@ export function useUsageData(days = 30) {
     };
   }, [refetch]);
 
-  const query = useQuery({
-    queryKey: ["usage", days],
-    queryFn: () => fetchSnapshots(days),
+  const daily = useQuery({
+    queryKey: ["usage", "daily", days, timeZone],
+    queryFn: () =>
+      invoke<DailyTotal[]>("get_daily_totals", {
+        range: lastDays(days, timeZone),
+        groupBy: "provider",
+      }),
   });
 
-  const snapshots = query.data ?? [];
-  const today = new Date().toISOString().slice(0, 10);
-
-  const todayAnthropicCost = snapshots
-    .filter((s) => s.date === today && s.provider === "anthropic")
-    .reduce((sum, s) => sum + s.cost_usd, 0);
-
-  const todayOpenaiCost = snapshots
-    .filter((s) => s.date === today && s.provider === "openai")
-    .reduce((sum, s) => sum + s.cost_usd, 0);
+  const models = useQuery({
+    queryKey: ["usage", "models", days, timeZone],
+    queryFn: () =>
+      invoke<ModelTotal[]>("get_model_totals", {
+        range: lastDays(days, timeZone),
+      }),
+  });
 
-  const todayClaudeCodeCost = snapshots
-    .filter((s) => s.date === today && s.provider === "claude_code")
-    .reduce((sum, s) => sum + s.cost_usd, 0);
+  const billing = useQuery({
+    queryKey: ["usage", "billing", days, timeZone],
+    queryFn: () =>
+      invoke<CostComparison[]>("get_cost_comparison", {
+        range: lastDays(days, timeZone),
+      }),
+  });
 
-  const todayAnthropicTokens = snapshots
-    .filter((s) => s.date === today && s.provider === "anthropic")
-    .reduce((sum, s) => sum + s.input_tokens + s.output_tokens, 0);
+  const today = useQuery({
+    queryKey: ["usage", "period", "today", timeZone],
+    queryFn: () => invoke<PeriodSummary>("get_period_summary", { period: "today" }),
+  });
 
-  const todayOpenaiTokens = snapshots
-    .filter((s) => s.date === today && s.provider === "openai")
-    .reduce((sum, s) => sum + s.input_tokens + s.output_tokens, 0);
+  const todayByProvider: Record<string, ProviderToday> = {};
+  for (const p of today.data?.by_provider ?? []) {
+    const id = !split && p.provider === SUBAGENTS.id ? "claude_code" : p.provider;
+    const prev = todayByProvider[id] ?? { cost: 0, tokens: 0 };
+    todayByProvider[id] = {
+      cost: prev.cost + p.cost_usd,
+      tokens: prev.tokens + p.tokens,
+    };
+  }
 
-  const todayClaudeCodeTokens = snapshots
-    .filter((s) => s.date === today && s.provider === "claude_code")
-    .reduce((sum, s) => sum + s.input_tokens + s.output_tokens, 0);
+  const dailyTotals = daily.data ?? [];
+  const modelTotals = models.data ?? [];
+  return {
+    dailyTotals: split ? dailyTotals : foldSubagents(dailyTotals),
+    modelTotals: split ? modelTotals : foldSubagentModels(modelTotals),
+    costComparison: billing.data ?? [],
+    todayByProvider,
+    todayTotal: today.data?.cost_usd ?? 0,
+    isLoading: daily.isLoading || models.isLoading || today.isLoading,
+    refetch,
+  };
+}
 
+// Every hour of `date` (YYYY-MM-DD) in the configured timezone, refreshed
+// with the rest of the usage queries on usage-updated
+export function useHourlyTimeline(date: string) {
+  const timeZone = useTimeZone();
+  const split = useSplitSubagents();
+  const query = useQuery({
+    queryKey: ["usage", "hourly", date, timeZone],
+    queryFn: () =>
+      invoke<HourlyTotal[]>("get_hourly_timeline", { date, groupBy: "provider" }),
+  });
+  const hourly = query.data ?? [];
   return {
-    snapshots,
-    dailyTotals: aggregateDailyTotals(snapshots),
-    modelTotals: aggregateModelTotals(snapshots),
-    todayAnthropicCost,
-    todayOpenaiCost,
-    todayClaudeCodeCost,
-    todayAnthropicTokens,
-    todayOpenaiTokens,
-    todayClaudeCodeTokens,
+    hourly: split ? hourly : foldSubagents(hourly),
     isLoading: query.isLoading,
-    refetch,
+  };
+}
+
+// The Claude Code projects and sessions that cost the most over the same
+// window as useUsageData
+export function useClaudeCodeBreakdown(days = 30) {
+  const timeZone = useTimeZone();
+  const projects = useQuery({
+    queryKey: ["usage", "projects", days, timeZone],
+    queryFn: () =>
+      invoke<ProjectTotal[]>("get_top_projects", {
+        range: lastDays(days, timeZone),
+      }),
+  });
+  const sessions = useQuery({
+    queryKey: ["usage", "sessions", days, timeZone],
+    queryFn: () =>
+      invoke<SessionTotal[]>("get_top_sessions", {
+        range: lastDays(days, timeZone),
+      }),
+  });
+  const profiles = useQuery({
+    queryKey: ["usage", "profiles", days, timeZone],
+    queryFn: () =>
+      invoke<ProfileTotal[]>("get_claude_code_profiles", {
+        range: lastDays(days, timeZone),
+      }),
+  });
+  return {
+    projects: projects.data ?? [],
+    sessions: sessions.data ?? [],
+    profiles: profiles.data ?? [],
+    isLoading: projects.isLoading || sessions.isLoading || profiles.isLoading,
   };
 }
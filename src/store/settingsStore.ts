@@ -5,6 +5,13 @@ export interface Settings {
   anthropic_key: string;
   openai_key: string;
   poll_interval_secs: string;
+  history_days: string;
+  // IANA zone days are counted in; empty means the system zone
+  timezone: string;
+  // "true" to chart Claude Code subagent runs as their own series
+  split_subagents: string;
+  // Further Claude Code config directories, one per line, as path or label=path
+  claude_config_dirs: string;
   alert_daily_usd: string;
   alert_monthly_usd: string;
 }
@@ -20,6 +27,10 @@ const defaults: Settings = {
   anthropic_key: "",
   openai_key: "",
   poll_interval_secs: "300",
+  history_days: "30",
+  timezone: "",
+  split_subagents: "",
+  claude_config_dirs: "",
   alert_daily_usd: "",
   alert_monthly_usd: "",
 };
@@ -40,10 +51,7 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
   save: async (updates) => {
     const merged = { ...get().settings, ...updates };
     set({ settings: merged });
-    await invoke("save_settings", {
-      settings: Object.fromEntries(
-        Object.entries(merged).filter(([, v]) => v !== ""),
-      ),
-    });
+    // Empty values are sent too: they clear the stored setting
+    await invoke("save_settings", { settings: merged });
   },
 }));